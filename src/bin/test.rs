@@ -1,5 +1,5 @@
 use serde_json::Serializer;
-use custom_json_formatter::OpenStructures;
+use custom_json_formatter::{CompactPrettyFormatter, Dialect, OpenStructures, SchemaComments};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -70,5 +70,53 @@ fn main() -> Result<(), serde_json::Error> {
     value.serialize(&mut serializer)?;
     println!("{}", String::from_utf8(target).unwrap());
 
+
+    println!("Compact pretty:");
+    let mut target = Vec::<u8>::new();
+    let mut serializer = Serializer::with_formatter(
+        &mut target,
+        CompactPrettyFormatter::new(60, "  ", true)
+    );
+    value.serialize(&mut serializer)?;
+    println!("{}", String::from_utf8(target).unwrap());
+
+
+    println!("JSON5:");
+    let mut target = Vec::<u8>::new();
+    let mut serializer = Serializer::with_formatter(
+        &mut target,
+        CompactPrettyFormatter::new(60, "  ", true)
+            .with_dialect(Dialect::Json5 { single_quotes: true })
+    );
+    value.serialize(&mut serializer)?;
+    println!("{}", String::from_utf8(target).unwrap());
+
+
+    println!("JSONC from schema:");
+    let schema = serde_json::json!({
+        "$ref": "#/definitions/A",
+        "definitions": {
+            "A": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string", "description": "name of the layer"},
+                    "childs": {
+                        "type": "array",
+                        "description": "the layers below",
+                        "items": {"$ref": "#/definitions/A"}
+                    }
+                }
+            }
+        }
+    });
+    let mut target = Vec::<u8>::new();
+    let mut serializer = Serializer::with_formatter(
+        &mut target,
+        CompactPrettyFormatter::new(60, "  ", true)
+            .with_comments(SchemaComments::new(&schema).into())
+    );
+    value.serialize(&mut serializer)?;
+    println!("{}", String::from_utf8(target).unwrap());
+
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,166 @@
+use crate::dialect::Dialect;
+use crate::escape::EscapePolicy;
+use std::fmt::Write;
+
+/// How byte arrays (`serialize_bytes`) are written.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub enum ByteArrays {
+    /// An array of numbers laid out like every other array.
+    #[default]
+    Numbers,
+    /// A string with the standard base64 encoding of the bytes, with padding.
+    Base64,
+    /// A string with two lowercase hex digits per byte.
+    Hex,
+    /// An array of numbers whose lines are filled with as many numbers as fit in this width,
+    /// counted from the start of the line. Inside a structure written in one line it is written
+    /// in one line too.
+    Packed(usize),
+}
+
+/// The indentation of a packed array.
+pub(crate) struct PackedLines<'a> {
+    pub line_break: &'a str,
+    /// written after every line break, followed by the indentation
+    pub line_prefix: &'a str,
+    /// in front of the elements
+    pub indent: String,
+    /// in front of the closing bracket
+    pub close_indent: String,
+    /// the column of the opening bracket
+    pub column: usize,
+}
+
+impl ByteArrays {
+    /// Renders `bytes` as string, None if they are written as array.
+    pub(crate) fn render(&self, bytes: &[u8], dialect: &Dialect, escaping: &impl EscapePolicy) -> Option<String> {
+        let mut rendered = String::new();
+        match self {
+            ByteArrays::Numbers | ByteArrays::Packed(_) => return None,
+            ByteArrays::Base64 => dialect.render_string(&base64(bytes), escaping, &mut rendered),
+            ByteArrays::Hex => {
+                let mut hex = String::with_capacity(bytes.len() * 2);
+                for byte in bytes {
+                    write!(hex, "{:02x}", byte).unwrap();
+                }
+                dialect.render_string(&hex, escaping, &mut rendered);
+            }
+        }
+        Some(rendered)
+    }
+}
+
+/// Renders `bytes` as packed array whose lines are filled up to `width`.
+/// Without `lines` or if they fit in the line they are written in one line.
+pub(crate) fn pack(bytes: &[u8], width: usize, lines: Option<PackedLines>) -> String {
+    let numbers: Vec<String> = bytes.iter().map(u8::to_string).collect();
+    let one_line = format!("[{}]", numbers.join(", "));
+    let lines = match lines {
+        Some(lines) if !bytes.is_empty() && lines.column + one_line.len() > width => lines,
+        _ => return one_line,
+    };
+    let mut rendered = String::from("[");
+    let mut line_length = usize::MAX;
+    for (index, number) in numbers.iter().enumerate() {
+        let last = index + 1 == numbers.len();
+        let length = number.len() + if last { 0 } else { 1 };
+        if index > 0 && line_length + 1 + length <= width {
+            rendered.push(' ');
+            line_length += 1;
+        } else {
+            rendered.push_str(lines.line_break);
+            rendered.push_str(lines.line_prefix);
+            rendered.push_str(&lines.indent);
+            line_length = lines.line_prefix.len() + lines.indent.len();
+        }
+        rendered.push_str(number);
+        if !last {
+            rendered.push(',');
+        }
+        line_length += length;
+    }
+    rendered.push_str(lines.line_break);
+    rendered.push_str(lines.line_prefix);
+    rendered.push_str(&lines.close_indent);
+    rendered.push(']');
+    rendered
+}
+
+fn base64(bytes: &[u8]) -> String {
+    static ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{format, Bytes};
+    use crate::{ByteArrays, CompactPrettyFormatter, Dialect, OpenStructures};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn base64_with_padding() {
+        let formatter = || OpenStructures::new("  ", 0).with_byte_arrays(ByteArrays::Base64);
+        assert_eq!(format(formatter(), &Bytes(b"".to_vec())), r#""""#);
+        assert_eq!(format(formatter(), &Bytes(b"f".to_vec())), r#""Zg==""#);
+        assert_eq!(format(formatter(), &Bytes(b"fo".to_vec())), r#""Zm8=""#);
+        assert_eq!(format(formatter(), &Bytes(b"foo".to_vec())), r#""Zm9v""#);
+        assert_eq!(format(formatter(), &Bytes(vec![0xfb, 0xff, 0xbf])), r#""+/+/""#);
+    }
+
+    #[test]
+    fn hex() {
+        let value = (Bytes(vec![0, 15, 16, 255]), Bytes(vec![]));
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_dialect(Dialect::Json5 { single_quotes: true })
+            .with_byte_arrays(ByteArrays::Hex);
+        assert_eq!(format(formatter, &value), "['000f10ff', '']");
+    }
+
+    #[test]
+    fn numbers_by_default() {
+        let formatter = OpenStructures::new("  ", 0);
+        assert_eq!(format(formatter, &Bytes(vec![1, 2])), "[1, 2]");
+    }
+    #[test]
+    fn packed_only_in_expanded_structures() {
+        let value = (Bytes((0..12).collect()), 1);
+        let packed = "[\n  [\n    0, 1, 2, 3, 4,\n    5, 6, 7, 8, 9,\n    10, 11\n  ],\n  1\n]";
+        let formatter = OpenStructures::new("  ", 0).with_byte_arrays(ByteArrays::Packed(20));
+        assert_eq!(format(formatter, &value), "[[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], 1]");
+        let formatter = OpenStructures::new("  ", 1).with_byte_arrays(ByteArrays::Packed(20));
+        assert_eq!(format(formatter, &value), packed);
+        let formatter = OpenStructures::new("  ", 1)
+            .with_single_scalars_inline(true)
+            .with_byte_arrays(ByteArrays::Packed(20));
+        assert_eq!(format(formatter, &[Bytes((0..12).collect())]), "[[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]]");
+
+        let formatter = CompactPrettyFormatter::new(80, "  ", true).with_byte_arrays(ByteArrays::Packed(20));
+        assert_eq!(format(formatter, &value), "[[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], 1]");
+        let formatter = CompactPrettyFormatter::new(30, "  ", true).with_byte_arrays(ByteArrays::Packed(20));
+        assert_eq!(format(formatter, &value), packed);
+    }
+    #[test]
+    fn packed_lines_start_behind_the_key() {
+        // `  "datadatadatadatadata": ` takes 27 columns
+        let value: BTreeMap<_, _> = vec![("datadatadatadatadata", Bytes((0..8).collect()))].into_iter().collect();
+        let packed = "{\n  \"datadatadatadatadata\": [\n    0, 1, 2, 3, 4, 5, 6, 7\n  ]\n}";
+        let formatter = OpenStructures::new("  ", 1).with_byte_arrays(ByteArrays::Packed(30));
+        assert_eq!(format(formatter, &value), packed);
+        let formatter = CompactPrettyFormatter::new(40, "  ", true).with_byte_arrays(ByteArrays::Packed(30));
+        assert_eq!(format(formatter, &value), packed);
+    }
+}
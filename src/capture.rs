@@ -0,0 +1,43 @@
+use crate::dialect::unescape;
+use serde_json::ser::CharEscape;
+
+/// Collects the unescaped content of the string serde_json is currently writing.
+///
+/// serde_json hands strings over in pieces (fragments, escapes and for numeric map keys even
+/// numbers), but quoting and escaping can only be decided once the whole string is known.
+#[derive(Default)]
+pub(crate) struct StringCapture {
+    raw: String,
+    active: bool,
+}
+
+impl StringCapture {
+    pub fn begin(&mut self) {
+        if self.active {
+            panic!("started string before ending the last!")
+        }
+        self.active = true;
+        self.raw.clear();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn push_str(&mut self, fragment: &str) {
+        self.raw.push_str(fragment);
+    }
+
+    pub fn push_escape(&mut self, char_escape: CharEscape) {
+        self.raw.push(unescape(char_escape));
+    }
+
+    /// Finishes the string and returns its unescaped content.
+    pub fn end(&mut self) -> String {
+        if !self.active {
+            panic!("String to close was´nt opened!")
+        }
+        self.active = false;
+        std::mem::take(&mut self.raw)
+    }
+}
@@ -0,0 +1,121 @@
+use crate::path::Path;
+use std::collections::HashMap;
+
+type CommentCallback<'a> = Box<dyn Fn(&Path) -> Option<String> + 'a>;
+
+/// Supplies the comments written in front of object members and array elements.
+///
+/// In expanded structures a comment gets its own `//` lines above the value, in single line
+/// structures it is appended to the value as `/* */`.
+/// Comments are not part of JSON, the output is meant for JSONC or JSON5 consumers.
+#[derive(Default)]
+pub struct Comments<'a> {
+    by_pointer: HashMap<String, String>,
+    callback: Option<CommentCallback<'a>>,
+}
+
+impl<'a> Comments<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a comment for the value at `pointer`, a JSON Pointer like `/childs/0/name`.
+    pub fn with_comment(mut self, pointer: impl Into<String>, comment: impl Into<String>) -> Self {
+        self.by_pointer.insert(pointer.into(), comment.into());
+        self
+    }
+    /// Asks `callback` for the comment of every value which has no comment set by pointer.
+    pub fn with_callback(mut self, callback: impl Fn(&Path) -> Option<String> + 'a) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.by_pointer.is_empty() && self.callback.is_none()
+    }
+
+    /// the comment of the value at `path`
+    pub(crate) fn get(&self, path: &Path) -> Option<String> {
+        if !self.by_pointer.is_empty() {
+            if let Some(comment) = self.by_pointer.get(&path.json_pointer()) {
+                return Some(comment.clone());
+            }
+        }
+        self.callback.as_ref().and_then(|callback| callback(path))
+    }
+}
+
+/// Renders `comment` as a single `/* */` comment.
+pub(crate) fn block_comment(comment: &str) -> String {
+    let comment = comment.replace("*/", "* /").replace(['\r', '\n'], " ");
+    format!("/* {} */", comment)
+}
+
+/// Renders `comment` as `//` comments, one per line of the comment.
+pub(crate) fn line_comments(comment: &str) -> impl Iterator<Item = String> + '_ {
+    comment.lines().map(|line| format!("// {}", line).trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{Comments, CompactPrettyFormatter, FoldPolicy, Layout, LayoutRules, OpenStructures};
+    use serde_json::json;
+
+    fn comments<'a>() -> Comments<'a> {
+        Comments::new()
+            .with_comment("/a/x", "the x")
+            .with_comment("/a/y/1", "second\nline")
+            .with_callback(|path| Some(format!("at {}", path.json_pointer())).filter(|_| path.json_pointer() == "/b"))
+    }
+
+    #[test]
+    fn comments_in_own_lines_in_expanded_structures() {
+        let value = json!({"a": {"x": 1, "y": [true, false]}, "b": "text"});
+        let formatter = CompactPrettyFormatter::new(20, "  ", true).with_comments(comments());
+        assert_eq!(
+            format(formatter, &value),
+            "{\n  \"a\": {\n    // the x\n    \"x\": 1,\n    \"y\": [\n      true,\n      // second\n      // line\n      false\n    ]\n  },\n  // at /b\n  \"b\": \"text\"\n}"
+        );
+    }
+
+    #[test]
+    fn block_comments_in_single_line_structures() {
+        let value = json!({"a": {"x": 1, "y": [true, false]}, "b": "text"});
+        let formatter = OpenStructures::new("  ", 0).with_comments(comments());
+        assert_eq!(
+            format(formatter, &value),
+            r#"{"a": {"x": 1 /* the x */, "y": [true, false /* second line */]}, "b": "text" /* at /b */}"#
+        );
+    }
+
+    #[test]
+    fn comment_cannot_end_block_comment() {
+        let value = json!([1]);
+        let formatter = OpenStructures::new("  ", 0).with_comments(Comments::new().with_comment("/0", "a */ b"));
+        assert_eq!(format(formatter, &value), "[1 /* a * / b */]");
+    }
+    #[test]
+    fn comments_count_towards_the_length_of_a_line() {
+        let value = json!({"a": [1, 2], "b": 3});
+        let long = || Comments::new().with_comment("/a/0", "a long comment").with_comment("/b", "b");
+        let expanded = "{\n  \"a\": [\n    // a long comment\n    1,\n    2\n  ],\n  // b\n  \"b\": 3\n}";
+        // `[1 /* a long comment */, 2]` is longer than 20 characters, `[1, 2]` isn't
+        let formatter = OpenStructures::new("  ", 1)
+            .with_fold_policy(FoldPolicy::new().expand_to_depth(1).expand_above_length(20))
+            .with_comments(long());
+        assert_eq!(format(formatter, &value), expanded);
+        let rules = || LayoutRules::new().with_rule("$.a".parse().unwrap(), Layout::Width(20));
+        let formatter = OpenStructures::new("  ", 1).with_layout_rules(rules()).with_comments(long());
+        assert_eq!(format(formatter, &value), expanded);
+        let formatter = CompactPrettyFormatter::new(80, "  ", true).with_layout_rules(rules()).with_comments(long());
+        assert_eq!(format(formatter, &value), expanded);
+        // `{"a": [1, 2], "b": 3 /* b */}` is longer than 24 characters
+        let formatter = CompactPrettyFormatter::new(24, "  ", true)
+            .with_comments(Comments::new().with_comment("/b", "b"));
+        assert_eq!(format(formatter, &value), "{\n  \"a\": [1, 2],\n  // b\n  \"b\": 3\n}");
+        let formatter = OpenStructures::new("  ", 0)
+            .with_fold_policy(FoldPolicy::new().expand_above_length(24))
+            .with_comments(Comments::new().with_comment("/b", "b"));
+        assert_eq!(format(formatter, &value), "{\n  \"a\": [1, 2],\n  // b\n  \"b\": 3\n}");
+    }
+}
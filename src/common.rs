@@ -0,0 +1,261 @@
+use crate::bytes::ByteArrays;
+use crate::capture::StringCapture;
+use crate::comments::Comments;
+use crate::dialect::Dialect;
+use crate::elision::Elision;
+use crate::escape::EscapePolicy;
+use crate::event::Event;
+use crate::layout::{LayoutRules, LineEnding, TrailingComma};
+use crate::numbers::{BigIntegers, FloatFormats, MAX_SAFE_INTEGER};
+use crate::path::{Path, PathHook};
+use crate::preview::Preview;
+use crate::projection::Projection;
+use crate::redaction::Redaction;
+use serde_json::ser::Formatter;
+use std::io;
+
+/// The options both formatters have, everything but the escape policy which is kept apart to
+/// change it.
+pub(crate) struct Settings<'a> {
+    pub dialect: Dialect,
+    pub big_integers: BigIntegers,
+    pub big_integer_limit: u128,
+    pub float_formats: FloatFormats,
+    pub byte_arrays: ByteArrays,
+    pub preview: Preview,
+    pub redaction: Redaction<'a>,
+    pub projection: Projection,
+    pub trailing_comma: Option<TrailingComma>,
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub line_prefix: &'a str,
+    pub comments: Comments<'a>,
+    pub rules: LayoutRules,
+    pub path_hook: Option<PathHook<'a>>,
+}
+
+impl<'a> Settings<'a> {
+    pub fn new() -> Self {
+        Settings {
+            dialect: Dialect::Json,
+            big_integers: BigIntegers::Number,
+            big_integer_limit: MAX_SAFE_INTEGER,
+            float_formats: FloatFormats::default(),
+            byte_arrays: ByteArrays::Numbers,
+            preview: Preview::default(),
+            redaction: Redaction::default(),
+            projection: Projection::default(),
+            trailing_comma: None,
+            line_ending: LineEnding::Lf,
+            final_newline: false,
+            line_prefix: "",
+            comments: Comments::default(),
+            rules: LayoutRules::default(),
+            path_hook: None,
+        }
+    }
+    /// the configured trailing comma style, or the one of the dialect
+    pub fn trailing_comma(&self) -> TrailingComma {
+        self.trailing_comma.unwrap_or_else(|| self.dialect.trailing_comma())
+    }
+    /// Calls the path hook if `event` starts the value at `path`, `in_key` is true while an object
+    /// key is written.
+    pub fn call_path_hook(&mut self, event: &Event, in_key: bool, path: &Path) {
+        let starts_value = match event {
+            Event::Value(_) | Event::Float(..) | Event::Bytes(_) | Event::BeginArray | Event::BeginObject => true,
+            Event::String(_) => !in_key,
+            _ => false,
+        };
+        if let Some(hook) = self.path_hook.as_mut().filter(|_| starts_value) {
+            hook(path);
+        }
+    }
+}
+
+/// The state of the calls of the serializer which aren't events yet.
+#[derive(Default)]
+pub(crate) struct Input {
+    pub string: StringCapture,
+    // true while the serializer writes an object key
+    pub started_key: bool,
+    pub elision: Elision,
+}
+
+/// Turns the calls of the serializer into events, the formatters only differ in how they lay the
+/// events out.
+pub(crate) trait EventSource<'a>: Formatter {
+    type Escaping: EscapePolicy;
+
+    /// The shared settings, the escape policy and the state of the calls.
+    fn parts(&mut self) -> (&Settings<'a>, &Self::Escaping, &mut Input);
+    /// Writes an event which passed the elision.
+    fn layout<W: ?Sized + io::Write>(&mut self, writer: &mut W, event: Event) -> io::Result<()>;
+
+    /// Passes the event through the elision of the preview mode, the redaction and the projection.
+    fn event<W: ?Sized + io::Write>(&mut self, writer: &mut W, event: Event) -> io::Result<()> {
+        let (settings, _, input) = self.parts();
+        if !input.elision.is_active(&settings.preview, &settings.redaction, &settings.projection) {
+            return self.layout(writer, event);
+        }
+        let mut events = vec![];
+        input.elision.process(&settings.preview, &settings.redaction, &settings.projection, event, &mut events);
+        for event in events {
+            self.layout(writer, event)?;
+        }
+        Ok(())
+    }
+    /// Writes a number, bool, null or raw fragment.
+    ///
+    /// serde_json writes numeric object keys between `begin_string` and `end_string`, their
+    /// digits are appended to the key as they are, without the handling of big integers or the
+    /// float format.
+    fn write_scalar<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: &str) -> io::Result<()> {
+        let (_, _, input) = self.parts();
+        if input.string.is_active() {
+            input.string.push_str(value);
+            Ok(())
+        } else {
+            self.event(writer, Event::Value(value.to_string()))
+        }
+    }
+    /// Writes an integer whose absolute value is `magnitude`, see [`BigIntegers`].
+    fn write_integer<W: ?Sized + io::Write>(&mut self, writer: &mut W, digits: &str, magnitude: u128) -> io::Result<()> {
+        let (settings, escaping, input) = self.parts();
+        let rendered = if input.string.is_active() {
+            None
+        } else {
+            settings.big_integers.render(digits, magnitude, settings.big_integer_limit, &settings.dialect, escaping)
+        };
+        self.write_scalar(writer, rendered.as_deref().unwrap_or(digits))
+    }
+    /// Writes a finite float, it is rendered with the format of its path once it is written.
+    fn write_float<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: f64, shortest: &str) -> io::Result<()> {
+        let (_, _, input) = self.parts();
+        if input.string.is_active() {
+            self.write_scalar(writer, shortest)
+        } else {
+            self.event(writer, Event::Float(value, shortest.to_string()))
+        }
+    }
+    /// Ends the current string, the preview mode shortens it unless it is an object key.
+    fn finish_string<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let (settings, _, input) = self.parts();
+        let raw = input.string.end();
+        let raw = if input.started_key {
+            raw
+        } else {
+            settings.preview.truncate(&raw).into_owned()
+        };
+        self.event(writer, Event::String(raw))
+    }
+    /// Writes a byte array, as configured by [`ByteArrays`].
+    fn write_bytes<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: &[u8]) -> io::Result<()> {
+        let (settings, escaping, _) = self.parts();
+        if let ByteArrays::Packed(_) = settings.byte_arrays {
+            return self.event(writer, Event::Bytes(value.to_vec()));
+        }
+        if let Some(rendered) = settings.byte_arrays.render(value, &settings.dialect, escaping) {
+            return self.write_scalar(writer, &rendered);
+        }
+        self.begin_array(writer)?;
+        for (index, byte) in value.iter().enumerate() {
+            self.begin_array_value(writer, index == 0)?;
+            self.write_u8(writer, *byte)?;
+            self.end_array_value(writer)?;
+        }
+        self.end_array(writer)
+    }
+}
+
+/// The builder methods of the [`Settings`] and the escape policy, for the formatter `$formatter`
+/// with the fields `settings`, `options` and `escaping`.
+macro_rules! settings_builders {
+    ($formatter:ident) => {
+        /// Sets the flavour of JSON to write, see [`Dialect`].
+        pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+            self.settings.dialect = dialect;
+            self
+        }
+        /// Sets which characters of strings and keys get escaped beyond the required escapes, see
+        /// [`EscapePolicy`].
+        pub fn with_escaping<P: EscapePolicy>(self, escaping: P) -> $formatter<'a, P> {
+            $formatter::from_settings(self.settings, self.options, escaping)
+        }
+        /// Sets how 64 and 128 bit integers beyond `limit` are written, see [`BigIntegers`].
+        pub fn with_big_integers(mut self, big_integers: BigIntegers, limit: u128) -> Self {
+            self.settings.big_integers = big_integers;
+            self.settings.big_integer_limit = limit;
+            self
+        }
+        /// Sets how floats are written, see [`FloatFormat`].
+        pub fn with_float_format(mut self, format: FloatFormat) -> Self {
+            self.settings.float_formats.default = format;
+            self
+        }
+        /// Sets how floats at the paths matching `selector` are written, the first matching
+        /// selector wins over the ones added later and the format set by `with_float_format`.
+        pub fn with_float_format_at(mut self, selector: Selector, format: FloatFormat) -> Self {
+            self.settings.float_formats.rules.push((selector, format));
+            self
+        }
+        /// Sets how byte arrays are written, see [`ByteArrays`].
+        pub fn with_byte_arrays(mut self, byte_arrays: ByteArrays) -> Self {
+            self.settings.byte_arrays = byte_arrays;
+            self
+        }
+        /// Enables the lossy preview mode, see [`Preview`].
+        pub fn with_preview(mut self, preview: Preview) -> Self {
+            self.settings.preview = preview;
+            self
+        }
+        /// Replaces the values of secret members, see [`Redaction`].
+        pub fn with_redaction(mut self, redaction: Redaction<'a>) -> Self {
+            self.settings.redaction = redaction;
+            self
+        }
+        /// Drops members and elements or keeps only the selected ones, see [`Projection`].
+        pub fn with_projection(mut self, projection: Projection) -> Self {
+            self.settings.projection = projection;
+            self
+        }
+        /// Sets which structures get a comma after their last element.
+        /// Defaults to the style of the dialect.
+        pub fn with_trailing_comma(mut self, trailing_comma: TrailingComma) -> Self {
+            self.settings.trailing_comma = Some(trailing_comma);
+            self
+        }
+        /// Sets the line break written between lines, defaults to [`LineEnding::Lf`].
+        pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+            self.settings.line_ending = line_ending;
+            self
+        }
+        /// Writes a line break after the top level value.
+        pub fn with_final_newline(mut self, final_newline: bool) -> Self {
+            self.settings.final_newline = final_newline;
+            self
+        }
+        /// Sets a prefix written at the start of every line after the first one, e.g. `"/// "` to
+        /// embed the output in a doc comment.
+        pub fn with_line_prefix(mut self, line_prefix: &'a str) -> Self {
+            self.settings.line_prefix = line_prefix;
+            self
+        }
+        /// Sets the comments to write in front of object members and array elements.
+        pub fn with_comments(mut self, comments: Comments<'a>) -> Self {
+            self.settings.comments = comments;
+            self
+        }
+        /// Sets layout overrides for structures at specific paths, see [`LayoutRules`].
+        pub fn with_layout_rules(mut self, rules: LayoutRules) -> Self {
+            self.settings.rules = rules;
+            self
+        }
+        /// Calls `hook` with the path of every value right before the value is written, e.g. to
+        /// log or collect the paths of a document.
+        pub fn with_path_hook(mut self, hook: impl FnMut(&Path) + 'a) -> Self {
+            self.settings.path_hook = Some(Box::new(hook));
+            self
+        }
+    };
+}
+pub(crate) use settings_builders;
@@ -0,0 +1,163 @@
+use crate::escape::{escapes_any, EscapePolicy};
+use crate::layout::TrailingComma;
+use serde_json::ser::CharEscape;
+
+/// The flavour of JSON the formatters emit.
+///
+/// The dialect only changes how single tokens (strings, keys, numbers, separators) are rendered,
+/// the layout decisions of the formatters stay the same.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub enum Dialect {
+    /// Plain JSON as described in RFC 8259.
+    #[default]
+    Json,
+    /// JSON5, meant for human edited files:
+    /// - object keys which are ASCII identifiers, like `name` or `$id`, are written without quotes
+    /// - strings can be written with single quotes
+    /// - expanded structures get a trailing comma, unless configured otherwise
+    Json5 {
+        single_quotes: bool,
+    },
+}
+
+impl Dialect {
+    fn quote(&self) -> char {
+        match self {
+            Dialect::Json5 { single_quotes: true } => '\'',
+            _ => '"',
+        }
+    }
+
+    /// the trailing comma style used if the formatter doesn't configure one
+    pub(crate) fn trailing_comma(&self) -> TrailingComma {
+        match self {
+            Dialect::Json => TrailingComma::Never,
+            Dialect::Json5 { .. } => TrailingComma::Expanded,
+        }
+    }
+
+    /// renders a string value with the unescaped content `raw`
+    pub(crate) fn render_string(&self, raw: &str, escaping: &impl EscapePolicy, target: &mut String) {
+        write_quoted(raw, self.quote(), escaping, target);
+    }
+
+    /// renders an object key with the unescaped content `raw`
+    /// keys containing escaped characters are always quoted
+    pub(crate) fn render_key(&self, raw: &str, escaping: &impl EscapePolicy, target: &mut String) {
+        match self {
+            Dialect::Json5 { .. } if is_identifier(raw) && !escapes_any(escaping, raw) => {
+                target.push_str(raw)
+            }
+            _ => self.render_string(raw, escaping, target),
+        }
+    }
+}
+
+/// Returns the character serde_json escaped with `char_escape`.
+pub(crate) fn unescape(char_escape: CharEscape) -> char {
+    match char_escape {
+        CharEscape::Quote => '"',
+        CharEscape::ReverseSolidus => '\\',
+        CharEscape::Solidus => '/',
+        CharEscape::Backspace => '\u{8}',
+        CharEscape::FormFeed => '\u{c}',
+        CharEscape::LineFeed => '\n',
+        CharEscape::CarriageReturn => '\r',
+        CharEscape::Tab => '\t',
+        CharEscape::AsciiControl(byte) => byte as char,
+    }
+}
+
+/// Writes `raw` surrounded by `quote`, escaping `quote`, `\`, all control characters and the
+/// characters the policy asks for.
+fn write_quoted(raw: &str, quote: char, escaping: &impl EscapePolicy, target: &mut String) {
+    static HEX_DIGITS: [u8; 16] = *b"0123456789abcdef";
+
+    target.push(quote);
+    for c in raw.chars() {
+        match c {
+            '\\' => target.push_str("\\\\"),
+            '\u{8}' => target.push_str("\\b"),
+            '\u{c}' => target.push_str("\\f"),
+            '\n' => target.push_str("\\n"),
+            '\r' => target.push_str("\\r"),
+            '\t' => target.push_str("\\t"),
+            c if c == quote => {
+                target.push('\\');
+                target.push(c);
+            }
+            c if c < ' ' => {
+                let byte = c as u8;
+                target.push_str("\\u00");
+                target.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+                target.push(HEX_DIGITS[(byte & 0xF) as usize] as char);
+            }
+            c => {
+                if !escaping.escape(c, target) {
+                    target.push(c);
+                }
+            }
+        }
+    }
+    target.push(quote);
+}
+
+/// Checks if `key` is an ASCII ECMAScript IdentifierName and can therefore be used unquoted in
+/// JSON5.
+///
+/// Keys with other characters stay quoted, `char::is_alphabetic` and `char::is_alphanumeric`
+/// accept characters like `²` which ID_Start and ID_Continue don't.
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c == '$' || c == '_' || c.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|c| c == '$' || c == '_' || c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{CompactPrettyFormatter, Dialect, OpenStructures};
+    use serde_json::json;
+
+    #[test]
+    fn json5_keys_unquoted_if_identifiers() {
+        let value = json!({"plain": 1, "$_x1": 2, "with space": 3, "1st": 4, "": 5});
+        let formatter = OpenStructures::new("  ", 0).with_dialect(Dialect::Json5 { single_quotes: false });
+        assert_eq!(
+            format(formatter, &value),
+            r#"{"": 5, $_x1: 2, "1st": 4, plain: 1, "with space": 3}"#
+        );
+    }
+
+    #[test]
+    fn json5_non_ascii_keys_quoted() {
+        let value = json!({"m²": 1, "größe": 2, "ascii": 3});
+        let formatter = OpenStructures::new("  ", 0).with_dialect(Dialect::Json5 { single_quotes: false });
+        assert_eq!(format(formatter, &value), r#"{ascii: 3, "größe": 2, "m²": 1}"#);
+    }
+
+    #[test]
+    fn json5_single_quotes() {
+        let value = json!({"it's": "say \"hi\"", "key": 'x'.to_string()});
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_dialect(Dialect::Json5 { single_quotes: true });
+        assert_eq!(format(formatter, &value), r#"{'it\'s': 'say "hi"', key: 'x'}"#);
+    }
+
+    #[test]
+    fn json_keeps_quotes() {
+        let value = json!({"plain": "it's"});
+        let formatter = CompactPrettyFormatter::new(80, "  ", true);
+        assert_eq!(format(formatter, &value), r#"{"plain": "it's"}"#);
+    }
+
+    #[test]
+    fn json5_trailing_comma_in_expanded_structures() {
+        let value = json!({"a": [1, 2]});
+        let formatter = OpenStructures::new("  ", 1).with_dialect(Dialect::Json5 { single_quotes: false });
+        assert_eq!(format(formatter, &value), "{\n  a: [1, 2],\n}");
+    }
+}
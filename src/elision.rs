@@ -0,0 +1,536 @@
+use crate::event::Event;
+use crate::path::Path;
+use crate::preview::{DepthPlaceholder, ElisionMarker, Preview};
+use crate::projection::{Projection, Visibility};
+use crate::redaction::{Redaction, Replacement};
+use std::collections::VecDeque;
+
+// A structure which is currently written
+struct Frame {
+    array: bool,
+    // true if the structure is kept by the projection with everything inside
+    kept: bool,
+    // count of the elements started so far, without the dropped ones
+    elements: usize,
+    // the last elements behind the head with the count of dropped elements before each of them,
+    // the ones before them are elided
+    tail: VecDeque<(usize, Vec<Event>)>,
+    // the events of the element which is currently recorded for the tail
+    recording: Option<Vec<Event>>,
+    elided: usize,
+    // count of dropped elements between the elided ones
+    elided_dropped: usize,
+    // count of dropped elements since the last element
+    dropped: usize,
+}
+
+// A member or element whose begin is kept back until it is known if its value is a structure
+struct Held {
+    array: bool,
+    events: Vec<Event>,
+}
+
+// A value which is not written, it is replaced once it is complete
+struct Suppressed {
+    reason: Reason,
+    // count of unclosed structures of the value
+    open: usize,
+    // count of elements of the value if it is a structure
+    elements: usize,
+    // FNV-1a hash of the tokens of the value
+    hash: u64,
+}
+
+enum Reason {
+    // a structure below the maximal depth
+    Depth { array: bool },
+    Redacted,
+    // a member or element removed with its key and separators
+    Dropped { array: bool },
+}
+
+/// Removes the events the preview mode, the redaction and the projection don't write.
+///
+/// Elements behind the head of an array are recorded until the array ends, as only then it is
+/// known which of them belong to the tail.
+#[derive(Default)]
+pub(crate) struct Elision {
+    frames: Vec<Frame>,
+    suppressed: Option<Suppressed>,
+    // the path of the events which passed, independent of the elided ones
+    path: Path,
+    in_key: bool,
+    key: Option<String>,
+    held: Option<Held>,
+    // true if the value which begins next is kept by the projection with everything inside
+    kept: bool,
+}
+
+impl Elision {
+    /// Passes `event` on to `out`, or keeps it until it is known if it gets written.
+    pub fn process(
+        &mut self,
+        preview: &Preview,
+        redaction: &Redaction,
+        projection: &Projection,
+        event: Event,
+        out: &mut Vec<Event>,
+    ) {
+        if self.suppressed.is_some() {
+            self.suppress(preview, redaction, event, out);
+            return;
+        }
+        if let Some(held) = &mut self.held {
+            match event {
+                Event::EndObjectKey | Event::BeginObjectValue => {
+                    self.in_key = false;
+                    held.events.push(event);
+                    return;
+                }
+                Event::BeginArray | Event::BeginObject => {
+                    let held = self.held.take().unwrap();
+                    self.release(preview, redaction, held, out);
+                    if self.suppressed.is_some() {
+                        self.suppress(preview, redaction, event, out);
+                        return;
+                    }
+                }
+                _ => {
+                    // a scalar can't contain kept values
+                    let held = self.held.take().unwrap();
+                    self.start_suppression(Reason::Dropped { array: held.array });
+                    self.suppress(preview, redaction, event, out);
+                    return;
+                }
+            }
+        }
+        let top_level = matches!(event, Event::BeginArray | Event::BeginObject | Event::Value(_) | Event::Float(..) | Event::Bytes(_) | Event::String(_));
+        if top_level && self.frames.is_empty() {
+            self.redact(redaction, None);
+            if self.suppressed.is_some() {
+                self.suppress(preview, redaction, event, out);
+                return;
+            }
+        }
+        match event {
+            Event::BeginArray | Event::BeginObject => {
+                let array = matches!(event, Event::BeginArray);
+                if preview.depth_limit().is_some_and(|depth| self.frames.len() >= depth) {
+                    self.start_suppression(Reason::Depth { array });
+                    self.suppress(preview, redaction, event, out);
+                    return;
+                }
+                // the top level value is always written
+                let kept = match self.frames.is_empty() {
+                    true => projection.visibility(&self.path, false) != Visibility::Ancestor,
+                    false => self.kept,
+                };
+                if array {
+                    self.path.begin_array();
+                }
+                self.emit(event, out);
+                self.frames.push(Frame {
+                    array,
+                    kept,
+                    elements: 0,
+                    tail: VecDeque::new(),
+                    recording: None,
+                    elided: 0,
+                    elided_dropped: 0,
+                    dropped: 0,
+                });
+            }
+            Event::BeginArrayValue(_) => {
+                self.path.begin_element();
+                let in_kept = self.frames.last().expect("array element outside of array").kept;
+                match projection.visibility(&self.path, in_kept) {
+                    Visibility::Dropped => self.start_suppression(Reason::Dropped { array: true }),
+                    Visibility::Kept => {
+                        self.kept = true;
+                        self.begin_element(preview, out);
+                        self.redact(redaction, None);
+                    }
+                    Visibility::Ancestor => {
+                        self.kept = false;
+                        self.held = Some(Held { array: true, events: vec![] });
+                    }
+                }
+            }
+            Event::EndArrayValue => {
+                self.path.end_value();
+                self.emit(event, out);
+                let frame = self.frames.last_mut().expect("array element outside of array");
+                if let Some(element) = frame.recording.take() {
+                    frame.tail.push_back((frame.dropped, element));
+                    frame.dropped = 0;
+                    let tail = preview.array_elision().map_or(0, |(_, tail)| tail);
+                    if frame.tail.len() > tail {
+                        let (dropped, _) = frame.tail.pop_front().unwrap();
+                        frame.elided += 1;
+                        frame.elided_dropped += dropped;
+                    }
+                }
+            }
+            Event::EndArray | Event::EndObject => {
+                let frame = self.frames.pop().expect("closed structure wasn't opened");
+                if frame.array {
+                    self.path.end_array();
+                    self.write_tail(preview, frame, out);
+                }
+                self.emit(event, out);
+            }
+            Event::BeginObjectKey(_) => {
+                // written once it is known if the member is dropped
+                self.in_key = true;
+            }
+            Event::String(raw) if self.in_key => {
+                self.path.begin_member(raw.clone());
+                self.key = Some(raw.clone());
+                let in_kept = self.frames.last().expect("object key outside of object").kept;
+                match projection.visibility(&self.path, in_kept) {
+                    Visibility::Dropped => {
+                        self.in_key = false;
+                        self.start_suppression(Reason::Dropped { array: false });
+                    }
+                    Visibility::Kept => {
+                        self.kept = true;
+                        self.begin_member(out);
+                        self.emit(Event::String(raw), out);
+                    }
+                    Visibility::Ancestor => {
+                        self.kept = false;
+                        self.held = Some(Held { array: false, events: vec![Event::String(raw)] });
+                    }
+                }
+            }
+            Event::EndObjectKey => {
+                self.in_key = false;
+                self.emit(event, out);
+            }
+            Event::BeginObjectValue => {
+                self.emit(event, out);
+                let key = self.key.take();
+                self.redact(redaction, key.as_deref());
+            }
+            Event::EndObjectValue => {
+                self.path.end_value();
+                self.emit(event, out);
+            }
+            event => self.emit(event, out),
+        }
+    }
+
+    /// true if events may be kept back or removed
+    pub fn is_active(&self, preview: &Preview, redaction: &Redaction, projection: &Projection) -> bool {
+        preview.array_elision().is_some()
+            || preview.depth_limit().is_some()
+            || !redaction.is_empty()
+            || !projection.is_empty()
+    }
+
+    // writes the begin of an array element which isn't dropped, or starts to record it
+    fn begin_element(&mut self, preview: &Preview, out: &mut Vec<Event>) {
+        let frame = self.frames.last_mut().expect("array element outside of array");
+        let index = frame.elements;
+        frame.elements += 1;
+        let event = Event::BeginArrayValue(index == 0);
+        match preview.array_elision() {
+            Some((head, _)) if index >= head => frame.recording = Some(vec![event]),
+            _ => {
+                let dropped = std::mem::take(&mut frame.dropped);
+                if dropped > 0 {
+                    self.emit(Event::SkipElements(dropped), out);
+                }
+                self.emit(event, out);
+            }
+        }
+    }
+
+    // writes the begin of an object member which isn't dropped
+    fn begin_member(&mut self, out: &mut Vec<Event>) {
+        let frame = self.frames.last_mut().expect("object key outside of object");
+        let first = frame.elements == 0;
+        frame.elements += 1;
+        self.emit(Event::BeginObjectKey(first), out);
+    }
+
+    // writes the kept back begin of a member or element whose value is a structure
+    fn release(&mut self, preview: &Preview, redaction: &Redaction, held: Held, out: &mut Vec<Event>) {
+        let array = held.array;
+        if array {
+            self.begin_element(preview, out);
+        } else {
+            self.begin_member(out);
+        }
+        for event in held.events {
+            self.emit(event, out);
+        }
+        let key = self.key.take();
+        self.redact(redaction, key.as_deref().filter(|_| !array));
+    }
+
+    // starts to suppress the value which begins next if it is redacted
+    fn redact(&mut self, redaction: &Redaction, key: Option<&str>) {
+        if !redaction.is_empty() && redaction.redacts(key, &self.path) {
+            self.start_suppression(Reason::Redacted);
+        }
+    }
+
+    fn start_suppression(&mut self, reason: Reason) {
+        self.suppressed = Some(Suppressed {
+            reason,
+            open: 0,
+            elements: 0,
+            hash: 0xcbf2_9ce4_8422_2325,
+        });
+    }
+
+    // swallows the event of a suppressed value, writes the replacement once the value is complete
+    fn suppress(&mut self, preview: &Preview, redaction: &Redaction, event: Event, out: &mut Vec<Event>) {
+        let suppressed = self.suppressed.as_mut().unwrap();
+        // a dropped value ends with its separators, the others with their last token
+        let dropped = matches!(suppressed.reason, Reason::Dropped { .. });
+        let complete = match &event {
+            Event::BeginArray | Event::BeginObject => {
+                suppressed.open += 1;
+                false
+            }
+            Event::EndArray | Event::EndObject => {
+                suppressed.open -= 1;
+                suppressed.open == 0 && !dropped
+            }
+            Event::BeginArrayValue(_) | Event::BeginObjectKey(_) => {
+                if suppressed.open == 1 {
+                    suppressed.elements += 1;
+                }
+                false
+            }
+            Event::Value(_) | Event::Float(..) | Event::Bytes(_) | Event::String(_) => suppressed.open == 0 && !dropped,
+            Event::EndArrayValue | Event::EndObjectValue => suppressed.open == 0 && dropped,
+            _ => false,
+        };
+        suppressed.hash(&event);
+        if !complete {
+            return;
+        }
+        let suppressed = self.suppressed.take().unwrap();
+        let replacement = match suppressed.reason {
+            Reason::Depth { array } => Event::Value(suppressed.placeholder(array, preview.placeholder())),
+            Reason::Redacted => match redaction.replacement() {
+                Replacement::Mask => Event::String("***".to_string()),
+                Replacement::Hash => Event::String(format!("#{:016x}", suppressed.hash)),
+            },
+            Reason::Dropped { array } => {
+                self.path.end_value();
+                if array {
+                    self.frames.last_mut().expect("array element outside of array").dropped += 1;
+                }
+                return;
+            }
+        };
+        self.emit(replacement, out);
+    }
+
+    // writes the marker of the elided elements followed by the tail
+    fn write_tail(&mut self, preview: &Preview, frame: Frame, out: &mut Vec<Event>) {
+        let mut written = frame.elements - frame.elided - frame.tail.len();
+        if frame.elided > 0 {
+            let skipped = frame.elided + frame.elided_dropped;
+            match preview.marker() {
+                ElisionMarker::String => {
+                    self.emit(Event::BeginArrayValue(written == 0), out);
+                    self.emit(Event::String(format!("… {} more …", frame.elided)), out);
+                    self.emit(Event::EndArrayValue, out);
+                    self.emit(Event::SkipElements(skipped - 1), out);
+                    written += 1;
+                }
+                ElisionMarker::Comment => {
+                    self.emit(Event::Comment(format!("{} more", frame.elided)), out);
+                    self.emit(Event::SkipElements(skipped), out);
+                }
+            }
+        }
+        for (dropped, element) in frame.tail {
+            let mut events = element.into_iter();
+            // the element starts with its BeginArrayValue
+            events.next();
+            if dropped > 0 {
+                self.emit(Event::SkipElements(dropped), out);
+            }
+            self.emit(Event::BeginArrayValue(written == 0), out);
+            for event in events {
+                self.emit(event, out);
+            }
+            written += 1;
+        }
+    }
+
+    // passes the event on to the innermost element which is recorded or to out
+    fn emit(&mut self, event: Event, out: &mut Vec<Event>) {
+        match self.frames.iter_mut().rev().find_map(|frame| frame.recording.as_mut()) {
+            Some(recording) => recording.push(event),
+            None => out.push(event),
+        }
+    }
+}
+
+impl Suppressed {
+    fn placeholder(&self, array: bool, style: DepthPlaceholder) -> String {
+        let (open, close, unit) = if array { ("[", "]", "element") } else { ("{", "}", "key") };
+        let plural = if self.elements == 1 { "" } else { "s" };
+        match style {
+            DepthPlaceholder::Ellipsis => format!("{}…{}", open, close),
+            DepthPlaceholder::Count => format!("{}/* {} {}{} */{}", open, self.elements, unit, plural, close),
+        }
+    }
+
+    fn hash(&mut self, event: &Event) {
+        let token = match event {
+            Event::Value(value) | Event::Float(_, value) => value.as_bytes(),
+            Event::Bytes(bytes) => bytes,
+            Event::String(raw) => {
+                self.hash_bytes(b"\"");
+                raw.as_bytes()
+            }
+            Event::BeginArray => b"[",
+            Event::EndArray => b"]",
+            Event::BeginObject => b"{",
+            Event::EndObject => b"}",
+            Event::BeginArrayValue(false) | Event::BeginObjectKey(false) => b",",
+            Event::BeginObjectValue => b":",
+            _ => b"",
+        };
+        self.hash_bytes(token);
+    }
+    fn hash_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{
+        Comments, CompactPrettyFormatter, DepthPlaceholder, ElisionMarker, FloatFormat, Layout, LayoutRules, OpenStructures,
+        Preview, Projection, Redaction, Selector,
+    };
+    use serde_json::json;
+
+    fn selector(selector: &str) -> Selector {
+        selector.parse().unwrap()
+    }
+    // writes everything in one line
+    fn folded() -> OpenStructures<'static> {
+        OpenStructures::new("  ", 0)
+    }
+
+    #[test]
+    fn head_and_tail() {
+        let value: Vec<u32> = (0..10).collect();
+        let elided = |head, tail| format(folded().with_preview(Preview::new().elide_arrays(head, tail)), &value);
+        assert_eq!(elided(2, 3), r#"[0, 1, "… 5 more …", 7, 8, 9]"#);
+        assert_eq!(elided(0, 1), r#"["… 9 more …", 9]"#);
+        assert_eq!(elided(3, 0), r#"[0, 1, 2, "… 7 more …"]"#);
+        assert_eq!(elided(5, 5), "[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]");
+        assert_eq!(elided(9, 9), "[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]");
+        assert_eq!(format(folded().with_preview(Preview::new().elide_arrays(0, 0)), &json!([])), "[]");
+    }
+
+    #[test]
+    fn nested_arrays_in_tail() {
+        let value = json!([[1, 2, 3, 4], [5], [6, 7, 8, 9], [10, 11, 12]]);
+        let preview = Preview::new().elide_arrays(1, 1);
+        assert_eq!(
+            format(folded().with_preview(preview), &value),
+            r#"[[1, "… 2 more …", 4], "… 2 more …", [10, "… 1 more …", 12]]"#
+        );
+    }
+
+    #[test]
+    fn comment_marker() {
+        let value: Vec<u32> = (0..6).collect();
+        let preview = |head, tail| Preview::new().elide_arrays(head, tail).elision_marker(ElisionMarker::Comment);
+        // the marker stays in front of the tail
+        assert_eq!(format(folded().with_preview(preview(1, 1)), &value), "[0, /* 4 more */ 5]");
+        let formatter = CompactPrettyFormatter::new(80, "  ", true).with_preview(preview(1, 1));
+        assert_eq!(format(formatter, &value), "[0, /* 4 more */ 5]");
+        let formatter = CompactPrettyFormatter::new(5, "  ", true).with_preview(preview(1, 1));
+        assert_eq!(format(formatter, &value), "[\n  0,\n  // 4 more\n  5\n]");
+        // without a tail it is in front of the closing bracket
+        assert_eq!(format(folded().with_preview(preview(3, 0)), &value), "[0, 1, 2 /* 3 more */]");
+        let formatter = CompactPrettyFormatter::new(80, "  ", true).with_preview(preview(3, 0));
+        assert_eq!(format(formatter, &value), "[0, 1, 2 /* 3 more */]");
+    }
+
+    #[test]
+    fn marker_with_dropped_elements() {
+        let value: Vec<u32> = (0..10).collect();
+        let projection = || {
+            Projection::new()
+                .with_dropped(selector("$[1]"))
+                .with_dropped(selector("$[4]"))
+                .with_dropped(selector("$[8]"))
+        };
+        let preview = Preview::new().elide_arrays(2, 2);
+        assert_eq!(
+            format(folded().with_preview(preview).with_projection(projection()), &value),
+            r#"[0, 2, "… 3 more …", 7, 9]"#
+        );
+        // the layout rule still sees the original index of the element
+        let value = json!([[0], [1], [2], [3], [4], [5], [6]]);
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_preview(Preview::new().elide_arrays(1, 2))
+            .with_projection(projection())
+            .with_layout_rules(LayoutRules::new().with_rule(selector("$[5]"), Layout::Expand));
+        assert_eq!(format(formatter, &value), "[\n  [0],\n  \"… 2 more …\",\n  [\n    5\n  ],\n  [6]\n]");
+    }
+
+    #[test]
+    fn kept_path_behind_dropped_structures() {
+        let value = json!({"items": [{"name": "x", "id": 1}, 5, {"name": "y", "id": 2}]});
+        let projection = Projection::new().with_kept(selector("$.items[2].name"));
+        assert_eq!(format(folded().with_projection(projection), &value), r#"{"items": [{"name": "y"}]}"#);
+    }
+
+    #[test]
+    fn selectors_behind_dropped_member() {
+        let value = json!({"o": {"a": {"x": [1]}, "b": "secret", "c": 1.5}});
+        let projection = || Projection::new().with_dropped(selector("$.o.a"));
+        let redaction = || Redaction::new().with_selector(selector("$.o.b"));
+        let formatter = folded()
+            .with_projection(projection())
+            .with_redaction(redaction())
+            .with_float_format_at(selector("$.o.c"), FloatFormat::new().fixed_decimals(2));
+        assert_eq!(format(formatter, &value), r#"{"o": {"b": "***", "c": 1.50}}"#);
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_projection(projection())
+            .with_redaction(redaction())
+            .with_float_format_at(selector("$.o.c"), FloatFormat::new().fixed_decimals(2));
+        assert_eq!(format(formatter, &value), r#"{"o": {"b": "***", "c": 1.50}}"#);
+    }
+
+    #[test]
+    fn counted_depth_placeholders() {
+        let value = json!({"a": [[1], {"b": 1}, [], {"c": 1, "d": 2}]});
+        let preview = Preview::new().max_depth(2).depth_placeholder(DepthPlaceholder::Count);
+        assert_eq!(
+            format(folded().with_preview(preview), &value),
+            r#"{"a": [[/* 1 element */], {/* 1 key */}, [/* 0 elements */], {/* 2 keys */}]}"#
+        );
+    }
+
+    #[test]
+    fn comments_behind_dropped_structures() {
+        let value = json!({"o": {"a": {"x": 1}, "b": [[1], 2]}});
+        let formatter = OpenStructures::new("  ", 3)
+            .with_projection(Projection::new().with_dropped(selector("$.o.a")).with_dropped(selector("$.o.b[0]")))
+            .with_comments(Comments::new().with_comment("/o/b", "b").with_comment("/o/b/1", "second"));
+        assert_eq!(
+            format(formatter, &value),
+            "{\n  \"o\": {\n    // b\n    \"b\": [\n      // second\n      2\n    ]\n  }\n}"
+        );
+    }
+}
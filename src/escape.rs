@@ -0,0 +1,150 @@
+use std::fmt::Write;
+
+/// Decides which characters of strings and keys are escaped beyond the escapes JSON requires
+/// and how they are written.
+///
+/// The quote, `\` and control characters are always escaped by the formatters and never passed
+/// to the policy.
+pub trait EscapePolicy {
+    /// Writes the escaped form of `c` to `target` and returns true, or returns false to write
+    /// `c` as is.
+    fn escape(&self, c: char, target: &mut String) -> bool;
+}
+
+/// Only the escapes JSON requires, everything else is written as is.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Minimal;
+
+impl EscapePolicy for Minimal {
+    fn escape(&self, _c: char, _target: &mut String) -> bool {
+        false
+    }
+}
+
+/// Every non ASCII character is written as `\uXXXX`, characters outside of the Basic
+/// Multilingual Plane as surrogate pair, so the output is pure ASCII.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct AsciiOnly;
+
+impl EscapePolicy for AsciiOnly {
+    fn escape(&self, c: char, target: &mut String) -> bool {
+        if c.is_ascii() {
+            return false;
+        }
+        write_unicode_escape(c, target);
+        true
+    }
+}
+
+/// `<`, `>`, `&`, U+2028 and U+2029 are written as `\uXXXX`, so the output can be embedded in
+/// HTML `<script>` elements and JavaScript source.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HtmlSafe;
+
+impl EscapePolicy for HtmlSafe {
+    fn escape(&self, c: char, target: &mut String) -> bool {
+        if !matches!(c, '<' | '>' | '&' | '\u{2028}' | '\u{2029}') {
+            return false;
+        }
+        write_unicode_escape(c, target);
+        true
+    }
+}
+
+/// `/` is written as `\/`, like some encoders do to keep `</` out of the output.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct EscapeSolidus;
+
+impl EscapePolicy for EscapeSolidus {
+    fn escape(&self, c: char, target: &mut String) -> bool {
+        if c != '/' {
+            return false;
+        }
+        target.push_str("\\/");
+        true
+    }
+}
+
+/// Writes `c` as `\uXXXX`, as two of them for characters which need a surrogate pair in UTF-16.
+pub fn write_unicode_escape(c: char, target: &mut String) {
+    let mut units = [0; 2];
+    for unit in c.encode_utf16(&mut units) {
+        write!(target, "\\u{:04x}", unit).unwrap();
+    }
+}
+
+/// true if `policy` escapes any character of `raw`
+pub(crate) fn escapes_any(policy: &impl EscapePolicy, raw: &str) -> bool {
+    let mut scratch = String::new();
+    raw.chars().any(|c| policy.escape(c, &mut scratch))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{AsciiOnly, CompactPrettyFormatter, Dialect, EscapePolicy, EscapeSolidus, HtmlSafe, OpenStructures};
+    use serde_json::json;
+
+    #[test]
+    fn ascii_only() {
+        let value = json!({"schlüssel": "naïve 😀", "plain": "ascii"});
+        let formatter = OpenStructures::new("  ", 0).with_escaping(AsciiOnly);
+        assert_eq!(
+            format(formatter, &value),
+            r#"{"plain": "ascii", "schl\u00fcssel": "na\u00efve \ud83d\ude00"}"#
+        );
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_dialect(Dialect::Json5 { single_quotes: true })
+            .with_escaping(AsciiOnly);
+        assert_eq!(format(formatter, &value), r#"{plain: 'ascii', 'schl\u00fcssel': 'na\u00efve \ud83d\ude00'}"#);
+    }
+
+    #[test]
+    fn minimal_keeps_unicode() {
+        let value = json!(["naïve 😀", "\u{1}"]);
+        let formatter = OpenStructures::new("  ", 0);
+        assert_eq!(format(formatter, &value), r#"["naïve 😀", "\u0001"]"#);
+    }
+
+    #[test]
+    fn html_safe() {
+        let value = json!({"<key>": "</script> & \u{2028}ü"});
+        let formatter = CompactPrettyFormatter::new(80, "  ", true).with_escaping(HtmlSafe);
+        assert_eq!(
+            format(formatter, &value),
+            r#"{"\u003ckey\u003e": "\u003c/script\u003e \u0026 \u2028ü"}"#
+        );
+    }
+
+    #[test]
+    fn escape_solidus() {
+        let formatter = OpenStructures::new("  ", 0).with_escaping(EscapeSolidus);
+        assert_eq!(format(formatter, &json!({"a/b": "</p>"})), r#"{"a\/b": "<\/p>"}"#);
+    }
+
+    // writes digits as HTML character references
+    struct Digits;
+
+    impl EscapePolicy for Digits {
+        fn escape(&self, c: char, target: &mut String) -> bool {
+            if !c.is_ascii_digit() {
+                return false;
+            }
+            target.push_str(&format!("&#{};", c as u32));
+            true
+        }
+    }
+
+    #[test]
+    fn custom_policy() {
+        let value = json!({"a1": "b2", "c": "\"3\""});
+        let formatter = OpenStructures::new("  ", 0).with_escaping(Digits);
+        assert_eq!(format(formatter, &value), r#"{"a&#49;": "b&#50;", "c": "\"&#51;\""}"#);
+
+        // an identifier key with escaped characters is quoted
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_dialect(Dialect::Json5 { single_quotes: false })
+            .with_escaping(Digits);
+        assert_eq!(format(formatter, &value), r#"{"a&#49;": "b&#50;", c: "\"&#51;\""}"#);
+    }
+}
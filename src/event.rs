@@ -0,0 +1,29 @@
+// A call of the Formatter. The formatters turn the calls into events, so the events can be
+// filtered by the preview mode and recorded while the layout of a structure is undecided.
+pub(crate) enum Event {
+    // a rendered number, bool, null or raw fragment
+    Value(String),
+    // a finite float and its shortest rendering, it is rendered with the float format of its path
+    // once it is written
+    Float(f64, String),
+    // a byte array written with `ByteArrays::Packed`, its lines are filled once the layout of the
+    // surrounding structures is known
+    Bytes(Vec<u8>),
+    // the unescaped content of a string or key
+    String(String),
+    // the marker of elided elements, a comment written in front of the next value of the current
+    // structure, or in front of its closing bracket
+    Comment(String),
+    // the following elements of the current array are further behind in the serialized array
+    SkipElements(usize),
+    BeginArray,
+    EndArray,
+    BeginArrayValue(bool),
+    EndArrayValue,
+    BeginObject,
+    EndObject,
+    BeginObjectKey(bool),
+    EndObjectKey,
+    BeginObjectValue,
+    EndObjectValue,
+}
@@ -0,0 +1,267 @@
+use crate::path::Path;
+use crate::selector::Selector;
+
+/// Decides which structures get a comma after their last element.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TrailingComma {
+    /// Never write a trailing comma, as required by plain JSON.
+    Never,
+    /// Write a trailing comma in structures which are expanded over multiple lines.
+    Expanded,
+    /// Write a trailing comma in every non empty structure, also in single line ones.
+    Always,
+}
+
+impl TrailingComma {
+    /// true if a structure with at least one element gets a trailing comma
+    pub(crate) fn applies(&self, expanded: bool) -> bool {
+        match self {
+            TrailingComma::Never => false,
+            TrailingComma::Expanded => expanded,
+            TrailingComma::Always => true,
+        }
+    }
+}
+
+/// The line break written between two lines.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub enum LineEnding {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    CrLf,
+    /// `\r\n` on Windows, `\n` everywhere else
+    Native,
+}
+
+impl LineEnding {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Native if cfg!(windows) => "\r\n",
+            LineEnding::Native => "\n",
+        }
+    }
+}
+
+/// The separators written between the tokens of a structure.
+///
+/// Whitespace at the end of a line is never written, so in expanded structures trailing
+/// whitespace of `item` is dropped and `padding` is not used.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Separators<'a> {
+    /// between two elements, default `", "`
+    pub item: &'a str,
+    /// between an object key and its value, default `": "`
+    pub key_value: &'a str,
+    /// after the opening and before the closing bracket of non empty structures, e.g. `" "` for
+    /// `{ "a": 1 }`, default `""`
+    pub padding: &'a str,
+}
+
+impl<'a> Default for Separators<'a> {
+    fn default() -> Self {
+        Separators {
+            item: ", ",
+            key_value: ": ",
+            padding: "",
+        }
+    }
+}
+
+/// Decides which structures `OpenStructures` expands over multiple lines.
+///
+/// A structure is expanded if any of the set conditions holds, or if a structure inside of it is
+/// expanded. Conditions on the content need a lookahead: the structure is buffered until it or a
+/// structure inside of it exceeds a limit, or until it is closed.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FoldPolicy {
+    depth: u32,
+    elements: Option<usize>,
+    length: Option<usize>,
+}
+
+impl FoldPolicy {
+    /// A policy which folds every structure into one line.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Expands structures whose depth is at most `depth`, the top level structure has depth 1.
+    pub fn expand_to_depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+    /// Expands structures with more than `count` elements.
+    pub fn expand_above_elements(mut self, count: usize) -> Self {
+        self.elements = Some(count);
+        self
+    }
+    /// Expands structures which are longer than `length` characters when written in one line.
+    pub fn expand_above_length(mut self, length: usize) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    pub(crate) fn expands_depth(&self, depth: u32) -> bool {
+        depth <= self.depth
+    }
+    /// the limits on count of elements and length of a structure
+    pub(crate) fn content_limits(&self) -> (Option<usize>, Option<usize>) {
+        (self.elements, self.length)
+    }
+}
+
+/// A layout override for the structures selected by a [`LayoutRules`] entry.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Layout {
+    /// Write every element in its own line.
+    Expand,
+    /// Write the structure with everything inside in one line, rules for nested values are ignored.
+    Compact,
+    /// Expand the structure and the structures up to this many levels below it,
+    /// deeper structures are written in one line.
+    Fold(u32),
+    /// Write the structure in one line if that isn't longer than this many characters,
+    /// expand it otherwise.
+    Width(usize),
+}
+
+/// Layout overrides for the structures at specific paths.
+///
+/// When a structure is opened the formatters use the layout of the first rule whose selector
+/// matches the path of the structure.
+#[derive(Clone, Debug, Default)]
+pub struct LayoutRules {
+    rules: Vec<(Selector, Layout)>,
+}
+
+impl LayoutRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a rule, e.g. `with_rule("$.metadata".parse()?, Layout::Expand)`.
+    pub fn with_rule(mut self, selector: Selector, layout: Layout) -> Self {
+        self.rules.push((selector, layout));
+        self
+    }
+    fn get(&self, path: &Path) -> Option<Layout> {
+        self.rules.iter()
+            .find(|(selector, _)| selector.matches(path))
+            .map(|(_, layout)| *layout)
+    }
+    /// true if a rule may expand a structure below `path`
+    pub(crate) fn expand_below(&self, path: &Path) -> bool {
+        self.rules.iter()
+            .any(|(selector, layout)| *layout != Layout::Compact && selector.leads_to(path))
+    }
+}
+
+/// The layout of a structure, decided when it is opened.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Decision {
+    /// the formatter decides with its own logic
+    Auto,
+    Expand,
+    Compact,
+    /// expand if longer than this in one line
+    Width(usize),
+    /// like Width, but the elements of the expanded structure fill its lines up to this width,
+    /// only used for packed byte arrays
+    Fill(usize),
+}
+
+/// Keeps track of the decisions for all opened structures.
+#[derive(Clone, Default)]
+pub(crate) struct Decisions {
+    // the decision and the depth up to which an active Layout::Fold expands structures
+    open: Vec<(Decision, Option<usize>)>,
+}
+
+impl Decisions {
+    /// Decides the layout of the structure opened at `path`.
+    pub fn open(&mut self, rules: &LayoutRules, path: &Path) -> Decision {
+        let depth = self.open.len();
+        let fold = self.open.last().and_then(|(_, fold)| *fold);
+        let decision = match self.open.last() {
+            Some((Decision::Compact, _)) => (Decision::Compact, None),
+            _ => match rules.get(path) {
+                Some(Layout::Expand) => (Decision::Expand, fold),
+                Some(Layout::Compact) => (Decision::Compact, None),
+                Some(Layout::Fold(levels)) => (Decision::Expand, Some(depth + levels as usize)),
+                Some(Layout::Width(width)) => (Decision::Width(width), fold),
+                None => match fold {
+                    Some(fold) if depth <= fold => (Decision::Expand, Some(fold)),
+                    Some(_) => (Decision::Compact, None),
+                    None => (Decision::Auto, None),
+                },
+            },
+        };
+        self.open.push(decision);
+        decision.0
+    }
+    pub fn close(&mut self) {
+        self.open.pop();
+    }
+    /// count of the opened structures
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{Comments, CompactPrettyFormatter, Dialect, LineEnding, OpenStructures, TrailingComma};
+    use serde_json::json;
+
+    #[test]
+    fn trailing_comma_in_expanded_structures() {
+        let value = json!({"a": [1, 2], "b": []});
+        let formatter = OpenStructures::new("  ", 0).with_trailing_comma(TrailingComma::Expanded);
+        assert_eq!(format(formatter, &value), "{\"a\": [1, 2], \"b\": []}");
+
+        let formatter = CompactPrettyFormatter::new(16, "  ", true).with_trailing_comma(TrailingComma::Expanded);
+        assert_eq!(format(formatter, &value), "{\n  \"a\": [1, 2],\n  \"b\": [],\n}");
+    }
+
+    #[test]
+    fn trailing_comma_always_or_never() {
+        let value = json!({"a": [1, 2], "b": []});
+        let formatter = OpenStructures::new("  ", 0).with_trailing_comma(TrailingComma::Always);
+        assert_eq!(format(formatter, &value), "{\"a\": [1, 2,], \"b\": [],}");
+
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_dialect(Dialect::Json5 { single_quotes: false })
+            .with_trailing_comma(TrailingComma::Never);
+        assert_eq!(format(formatter, &value), "{a: [1, 2], b: []}");
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let value = json!({"a": [1, 2]});
+        let formatter = OpenStructures::new("  ", 2).with_line_ending(LineEnding::CrLf);
+        assert_eq!(format(formatter, &value), "{\r\n  \"a\": [\r\n    1,\r\n    2\r\n  ]\r\n}");
+
+        let comments = Comments::new().with_comment("/a/0", "one\ntwo");
+        let formatter = CompactPrettyFormatter::new(10, "  ", true)
+            .with_line_ending(LineEnding::CrLf)
+            .with_comments(comments);
+        assert_eq!(
+            format(formatter, &value),
+            "{\r\n  \"a\": [\r\n    // one\r\n    // two\r\n    1,\r\n    2\r\n  ]\r\n}"
+        );
+    }
+
+    #[test]
+    fn final_newline() {
+        let value = json!([1]);
+        let formatter = OpenStructures::new("  ", 0).with_final_newline(true);
+        assert_eq!(format(formatter, &value), "[1]\n");
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_line_ending(LineEnding::CrLf)
+            .with_final_newline(true);
+        assert_eq!(format(formatter, &json!("text")), "\"text\"\r\n");
+    }
+}
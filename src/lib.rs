@@ -1,8 +1,38 @@
 //This library is incomplete for now dead code is ok
 #![allow(dead_code)]
 #![allow(unused_variables)]
+mod bytes;
+mod capture;
+mod comments;
+mod common;
+mod dialect;
+mod elision;
+mod escape;
+mod event;
+mod layout;
 mod linefit;
+mod numbers;
 mod openstructures;
+mod path;
+mod preview;
+mod projection;
+mod redaction;
+mod schema;
+mod selector;
+#[cfg(test)]
+mod testing;
 
+pub use bytes::ByteArrays;
+pub use comments::Comments;
+pub use dialect::Dialect;
+pub use escape::{write_unicode_escape, AsciiOnly, EscapePolicy, EscapeSolidus, HtmlSafe, Minimal};
+pub use layout::{FoldPolicy, Layout, LineEnding, LayoutRules, Separators, TrailingComma};
 pub use linefit::CompactPrettyFormatter;
-pub use openstructures::OpenStructures;
\ No newline at end of file
+pub use numbers::{BigIntegers, FloatFormat, MAX_SAFE_INTEGER};
+pub use openstructures::OpenStructures;
+pub use path::{Path, PathSegment};
+pub use preview::{DepthPlaceholder, ElisionMarker, Preview};
+pub use projection::Projection;
+pub use redaction::{Redaction, Replacement};
+pub use schema::SchemaComments;
+pub use selector::{Selector, SelectorError};
\ No newline at end of file
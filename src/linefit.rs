@@ -1,6 +1,19 @@
+use crate::bytes::ByteArrays;
+use crate::comments::{block_comment, line_comments, Comments};
+use crate::common::{settings_builders, EventSource, Input, Settings};
+use crate::dialect::Dialect;
+use crate::escape::{EscapePolicy, Minimal};
+use crate::event::Event;
+use crate::numbers::{BigIntegers, FloatFormat};
+use crate::preview::{display_width, Preview};
+use crate::projection::Projection;
+use crate::redaction::Redaction;
+use crate::selector::Selector;
+use crate::layout::{Decision, Decisions, LayoutRules, LineEnding, TrailingComma};
+use crate::path::Path;
 use serde_json::ser::{Formatter, CharEscape};
 use std::io;
-use std::ops::RangeInclusive;
+use std::ops::Range;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum ElementType {
@@ -8,6 +21,11 @@ enum ElementType {
     Element,
     ObjectKey,
     CloseBracket,
+    // the comment of the following object member or array element
+    Comment,
+    // the marker of elided elements, unlike a comment it stays in front of the following element
+    // in a line
+    Marker,
 }
 
 impl ElementType {
@@ -17,242 +35,672 @@ impl ElementType {
             ElementType::Element => 0,
             ElementType::ObjectKey => 0,
             ElementType::CloseBracket => -1,
+            ElementType::Comment => 0,
+            ElementType::Marker => 0,
         }
     }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 struct Element {
+    // end of the element in cached_data, it starts at the end of the previous element
     end: usize,
-    complete_line_length: usize,
+    // display length of the element
+    length: usize,
     element_type: ElementType,
+    // the layout decided for an OpenBracket
+    decision: Decision,
 }
 
 
 
-pub struct CompactPrettyFormatter<'a> {
+//---------------Options---------------------
+// the options only this formatter has, the shared ones are in the Settings
+struct Options<'a> {
     //the longest allowed String
     line_break_with: usize,
     //
     indent: &'a str,
     //the impact of the indent to the line_length
     indent_impact: usize,
+    start_column: usize,
+    comma_first: bool,
+    hanging_indent: bool,
+}
 
+pub struct CompactPrettyFormatter<'a, E = Minimal> {
+    settings: Settings<'a>,
+    options: Options<'a>,
+    escaping: E,
     //---------------Changing---------------------
 
-    // write type
-    started_string: bool,
-    started_key: bool,
-
-    // count of unmatched opened brackets ('{' or '[') which doesnt appear in elements
-    written_unmatched_brackets: usize,
+    input: Input,
+    // like started_key of the input, but for the events passed the elision
+    in_key: bool,
+    path: Path,
+    decisions: Decisions,
+    // the decision for the next OpenBracket
+    decision: Decision,
+
+    // one entry for every written opened bracket ('{' or '[') which didn't fit in one line and
+    // therefore has its elements in separate lines, notes if an element of it is written already
+    written_unmatched_brackets: Vec<bool>,
+    // with comma first the line of the next element was started by its comment already
+    line_started: bool,
+    // with hanging indentation the column of the elements of every written bracket
+    hanging_columns: Vec<usize>,
+    // for a written packed byte array the width of its lines and the column after its last
+    // written element
+    filled_line: Option<(usize, usize)>,
 
-    // count of unmatched opened brackets ('{' or '[') in elements which doesnt have a counterpart
-    // inside of elements
-    // this value is negative if elements contains more closing than opening brackets!
-    unwritten_unmatched_brackets: isize,
     // Unwritten elements
     elements: Vec<Element>,
     // Unwritten data
-    cached_data: Vec<u8>,
+    cached_data: String,
 
-    current_element_end: usize,
+    // display length of the element which is currently written
     current_length: usize,
-
-    // notes if the current line contains already data => dont write indents again!
-    line_started: bool,
-
-    written_buffer_length: usize,
-    written_line_length: usize,
 }
 
 impl<'a> CompactPrettyFormatter<'a> {
     pub fn new(line_break_with: usize, indent: &'a str, include_indent: bool) -> Self {
-        let mut this = Self{
+        let options = Options {
             line_break_with,
             indent,
             indent_impact: 0,
-            started_string: false,
-            started_key: false,
-            written_unmatched_brackets: 0,
-            unwritten_unmatched_brackets: 0,
-            elements: vec![],
-            cached_data: vec![],
-            current_element_end: 0,
-            current_length: 0,
-            line_started: false,
-            written_buffer_length: 0,
-            written_line_length: 0
+            start_column: 0,
+            comma_first: false,
+            hanging_indent: false,
         };
+        let mut this = CompactPrettyFormatter::from_settings(Settings::new(), options, Minimal);
         let impact = if include_indent {
             this.display_length(indent)
         } else {
             0
         };
-        this.indent_impact = impact;
+        this.options.indent_impact = impact;
         this
     }
-    pub fn start_string(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
-        if !self.started_string {
-            self.started_string = true;
-            self.write_part("\"", writer)?;
-            Ok(())
-        } else {
-            panic!("started string before ending the last!")
+}
+
+impl<'a, E: EscapePolicy> CompactPrettyFormatter<'a, E> {
+    fn from_settings(settings: Settings<'a>, options: Options<'a>, escaping: E) -> Self {
+        CompactPrettyFormatter {
+            settings,
+            options,
+            escaping,
+            input: Input::default(),
+            in_key: false,
+            path: Path::default(),
+            decisions: Decisions::default(),
+            decision: Decision::Auto,
+            written_unmatched_brackets: vec![],
+            line_started: false,
+            hanging_columns: vec![],
+            filled_line: None,
+            elements: vec![],
+            cached_data: String::new(),
+            current_length: 0,
         }
     }
-    pub fn end_string(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
-        if self.started_string {
-            self.started_string = false;
-            self.write("\"", ElementType::Element, writer)?;
-            Ok(())
-        } else {
-            panic!("String to close was´nt opened!")
-        }
+    settings_builders!(CompactPrettyFormatter);
+    /// Sets the column at which the first line starts in the surrounding text, it counts
+    /// towards `line_break_with` like the line prefix does for the following lines.
+    pub fn with_start_column(mut self, start_column: usize) -> Self {
+        self.options.start_column = start_column;
+        self
+    }
+    /// Writes structures which don't fit in one line with leading commas, every element starts
+    /// its line with the comma:
+    /// ```text
+    /// [ 1
+    /// , 2
+    /// ]
+    /// ```
+    /// Trailing commas are never written in these structures.
+    pub fn with_comma_first(mut self, comma_first: bool) -> Self {
+        self.options.comma_first = comma_first;
+        self
     }
-    pub fn start_key(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
-        if !self.started_key {
-            self.started_key = true;
-            Ok(())
+    /// Aligns the elements of structures which don't fit in one line to the column after their
+    /// opening bracket instead of indenting them, the first element and the closing bracket stay
+    /// in the line of the bracket:
+    /// ```text
+    /// {"a": [1,
+    ///        2]}
+    /// ```
+    /// Replaces the comma first style, trailing commas are never written in these structures.
+    pub fn with_hanging_indent(mut self, hanging_indent: bool) -> Self {
+        self.options.hanging_indent = hanging_indent;
+        self
+    }
+    fn start_key(&mut self) {
+        if !self.input.started_key {
+            self.input.started_key = true;
         } else {
             panic!("started object key before ending the last!")
         }
     }
-    pub fn end_key(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
-        if self.started_key {
-            self.started_string = false;
-            self.write(":", ElementType::ObjectKey, writer)?;
-            Ok(())
+    fn end_key(&mut self) {
+        if self.input.started_key {
+            self.input.started_key = false;
         } else {
             panic!("object key to close was´nt opened!")
         }
     }
 
+    // returns the index of the last element of the value starting at index
+    // returns None if the value is a structure which isn't closed yet
     fn end_of_structure(&self, index: usize) -> Option<usize> {
-        let mut elements = self.elements.iter().zip(index..);
-        if elements.next().unwrap().0.element_type == ElementType::Element {
-            Some(index)
-        } else {
-            let mut open_brackets = 1;
-
-            for (element, index) in elements {
-                open_brackets += element.element_type.level();
-                if open_brackets == 0 {
-                    return Some(index)
-                }
+        let mut open_brackets = 0;
+        for (index, element) in self.elements.iter().enumerate().skip(index) {
+            open_brackets += element.element_type.level();
+            if open_brackets == 0 {
+                return Some(index)
             }
-            None
-        }
-    }
-    fn line_start_of(&self, index: usize) -> usize {
-        if index == 0 {
-            self.written_line_length
-        } else {
-            self.elements[index - 1].complete_line_length
         }
+        None
     }
     fn buffer_start_of(&self, index: usize) -> usize {
         if index == 0 {
-            self.written_buffer_length
+            0
         } else {
             self.elements[index - 1].end
         }
     }
-
-    // returns the indices the the elements which describe the first full structure
-    // ('{...}' or '[...]') that is part of a structure which is known to not fit in display width
-    // returns None if we dont know if a unclosed structure will maybe fit, Some otherwise
-    //
-    fn first_full_structure(&self) -> Option<RangeInclusive<usize>> {
-        let elements = self.elements.iter().enumerate();
-
-        let mut opened_brackets = self.written_unmatched_brackets;
-
-        for (index, element) in elements {
-            if let Some(end) = self.end_of_structure(index) {
-                //Structure fits!
-                if self.line_break_with > self.indent_impact * opened_brackets + self.elements[end].complete_line_length - self.line_start_of(index) || index == end {
-                    return Some(index..=end)
+    /// the separator between two elements if both are written in the same line
+    fn separator(&self, previous: ElementType, next: ElementType) -> &'static str {
+        match (previous, next) {
+            (ElementType::ObjectKey, _) => ": ",
+            (ElementType::OpenBracket, _) => "",
+            (_, ElementType::CloseBracket) if self.settings.trailing_comma().applies(false) => ",",
+            (_, ElementType::CloseBracket) => "",
+            _ => ", ",
+        }
+    }
+    fn data_of(&self, index: usize) -> &str {
+        &self.cached_data[self.buffer_start_of(index)..self.elements[index].end]
+    }
+    // a comment is written after the member or element it belongs to if it is written in one line
+    // returns the index of the last element of that member, None if it isn't complete yet
+    // a comment in front of a closing bracket belongs to no value, it is written where it is
+    fn commented_end(&self, comment: usize) -> Option<usize> {
+        let mut value = comment + 1;
+        match self.elements.get(value).map(|element| element.element_type) {
+            Some(ElementType::ObjectKey) => value += 1,
+            Some(ElementType::CloseBracket) => return Some(comment),
+            _ => {}
+        }
+        self.end_of_structure(value)
+    }
+    // the display length of the elements in range written in one line
+    fn line_length(&self, range: Range<usize>) -> usize {
+        let mut length = 0;
+        let mut previous = None;
+        for (index, element) in self.elements.iter().enumerate().take(range.end).skip(range.start) {
+            if element.element_type == ElementType::Comment {
+                if self.commented_end(index).is_some_and(|end| end < range.end) {
+                    length += 1 + self.display_length(&block_comment(self.data_of(index)));
                 }
+                continue;
             }
-            opened_brackets = (opened_brackets as isize + element.element_type.level()) as usize;
+            if element.element_type == ElementType::Marker {
+                length += 1 + self.display_length(&block_comment(self.data_of(index)));
+                continue;
+            }
+            if let Some(previous) = previous {
+                length += self.separator(previous, element.element_type).len();
+            }
+            length += element.length;
+            previous = Some(element.element_type);
         }
-        None
+        length
     }
 
-
     pub fn display_length(&self, string: &str) -> usize {
-        string.len()
+        display_width(string)
     }
     pub fn write_indents(&self, count: usize, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
-        for i in 0..count {
-            writer.write(self.indent.as_bytes())?;
+        for _ in 0..count {
+            writer.write_all(self.options.indent.as_bytes())?;
         }
         Ok(())
     }
 
-    pub fn write_in_line(&mut self, elements: usize, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
-        self.elements.iter().take(elements).for_each(|element|{
-
-        });
+    // starts the line of the next element in the innermost written structure
+    fn start_line(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        let depth = self.written_unmatched_brackets.len();
+        if self.options.hanging_indent {
+            return self.start_hanging_line(writer);
+        }
+        if self.options.comma_first {
+            return self.start_comma_first_line(writer);
+        }
+        if let Some(has_element) = self.written_unmatched_brackets.last_mut() {
+            if *has_element {
+                writer.write_all(b",")?;
+            }
+            *has_element = true;
+            self.write_line_break(writer)?;
+            self.write_indents(depth, writer)?;
+        }
         Ok(())
     }
 
-    pub fn write_back_overflowing_elements(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+    // the first element follows the opening bracket, the others are aligned to it
+    fn start_hanging_line(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        let line_started = std::mem::take(&mut self.line_started);
+        if let Some(has_element) = self.written_unmatched_brackets.last_mut() {
+            let has_element = std::mem::replace(has_element, true);
+            if has_element && !line_started {
+                writer.write_all(b",")?;
+            }
+            if has_element || line_started {
+                self.write_line_break(writer)?;
+                self.write_element_indents(writer)?;
+            }
+        }
         Ok(())
     }
 
-    pub fn write_part<W: ?Sized + io::Write>(&mut self, data: &str, writer: &mut W) -> io::Result<()> {
-        self.current_length += self.display_length(data);
+    // writes a line break followed by the line prefix.
+    fn write_line_break(&self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        writer.write_all(self.settings.line_ending.as_str().as_bytes())?;
+        writer.write_all(self.settings.line_prefix.as_bytes())
+    }
 
-        self.cached_data.extend_from_slice(data.as_bytes());
+    // the indentation of the lines of the elements in the innermost written structure
+    fn write_element_indents(&self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        match self.hanging_columns.last() {
+            Some(&column) => {
+                let column = column.saturating_sub(self.display_length(self.settings.line_prefix));
+                writer.write_all(" ".repeat(column).as_bytes())
+            }
+            None => self.write_indents(self.written_unmatched_brackets.len(), writer),
+        }
+    }
 
-        //write if the current length exceeds the maximal width
-        if self.current_length as isize + (self.unwritten_unmatched_brackets) * self.indent_impact as isize > self.line_break_with as isize {
-            self.write_back_overflowing_elements(writer)?;
+    // the first element follows the opening bracket, the others follow the comma at its column,
+    // both filled up to the width of one indent
+    fn start_comma_first_line(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        let depth = self.written_unmatched_brackets.len();
+        let fill = self.display_length(self.options.indent).saturating_sub(1).max(1);
+        let line_started = std::mem::take(&mut self.line_started);
+        if let Some(has_element) = self.written_unmatched_brackets.last_mut() {
+            let has_element = std::mem::replace(has_element, true);
+            if line_started {
+                self.write_line_break(writer)?;
+                self.write_indents(depth, writer)?;
+                return Ok(());
+            }
+            if has_element {
+                self.write_line_break(writer)?;
+                self.write_indents(depth - 1, writer)?;
+                writer.write_all(b",")?;
+            }
+            writer.write_all(" ".repeat(fill).as_bytes())?;
         }
+        Ok(())
+    }
 
+    // writes the comment in front of the cache in own lines
+    fn write_comment_lines(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        let depth = self.written_unmatched_brackets.len();
+        if (self.options.comma_first || self.options.hanging_indent) && depth > 0 {
+            // the comment takes the place of the element behind the comma or bracket
+            self.start_line(writer)?;
+            for (index, line) in line_comments(self.data_of(0)).enumerate() {
+                if index > 0 {
+                    self.write_line_break(writer)?;
+                    self.write_element_indents(writer)?;
+                }
+                writer.write_all(line.as_bytes())?;
+            }
+            self.line_started = true;
+            self.remove_elements(1);
+            return Ok(());
+        }
+        if let Some(has_element) = self.written_unmatched_brackets.last_mut() {
+            if *has_element {
+                writer.write_all(b",")?;
+            }
+            // the comma is written already
+            *has_element = false;
+        }
+        for line in line_comments(self.data_of(0)) {
+            if depth > 0 {
+                self.write_line_break(writer)?;
+                self.write_indents(depth, writer)?;
+            }
+            writer.write_all(line.as_bytes())?;
+        }
+        self.remove_elements(1);
         Ok(())
     }
 
-    fn write<W: ?Sized + io::Write>(&mut self, data: &str, element_type: ElementType, writer: &mut W) -> io::Result<()> {
-        let result = self.write_part(data, writer);
-
-        if !self.started_key && !self.started_string {
-            let open_impact = match element_type {
-                ElementType::OpenBracket => 1,
-                ElementType::Element => 0,
-                ElementType::ObjectKey => 0,
-                ElementType::CloseBracket => -1,
-            };
+    // writes the first count elements in one line and removes them from the cache
+    fn write_in_line(&mut self, count: usize, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        let mut line = String::new();
+        let mut comments = vec![];
+        let mut marker = None;
+        let mut previous = None;
+        for index in 0..count {
+            let element_type = self.elements[index].element_type;
+            if element_type == ElementType::Comment {
+                match self.commented_end(index) {
+                    Some(end) if end == index => {
+                        line.push(' ');
+                        line.push_str(&block_comment(self.data_of(index)));
+                    }
+                    Some(end) => comments.push((end, index)),
+                    None => {}
+                }
+                continue;
+            }
+            if element_type == ElementType::Marker {
+                marker = Some(block_comment(self.data_of(index)));
+                continue;
+            }
+            if let Some(previous) = previous {
+                line.push_str(self.separator(previous, element_type));
+            }
+            if let Some(marker) = marker.take() {
+                if element_type == ElementType::CloseBracket {
+                    line.push(' ');
+                    line.push_str(&marker);
+                } else {
+                    line.push_str(&marker);
+                    line.push(' ');
+                }
+            }
+            line.push_str(self.data_of(index));
+            previous = Some(element_type);
+            for &(_, comment) in comments.iter().filter(|(end, _)| *end == index) {
+                line.push(' ');
+                line.push_str(&block_comment(self.data_of(comment)));
+            }
+        }
+        writer.write_all(line.as_bytes())?;
+        self.remove_elements(count);
+        Ok(())
+    }
 
-            self.elements.push(Element {
-                end: self.current_element_end,
-                complete_line_length: self.current_length,
-                element_type
+    fn remove_elements(&mut self, count: usize) {
+        let written = self.buffer_start_of(count);
+        self.cached_data.drain(..written);
+        self.elements.drain(..count);
+        for element in &mut self.elements {
+            element.end -= written;
+        }
+    }
+
+    // writes all cached elements whose layout is known:
+    // - values which are complete and fit in a line
+    // - opening brackets of structures which are known to not fit in a line
+    // - closing brackets of structures which didn't fit
+    fn write_back_overflowing_elements(&mut self, writer: &mut (impl ?Sized + io::Write)) -> io::Result<()> {
+        while let Some(first) = self.elements.first() {
+            if first.element_type == ElementType::CloseBracket {
+                let has_element = self.written_unmatched_brackets.pop()
+                    .expect("closed structure was´nt opened!");
+                self.filled_line = None;
+                if self.hanging_columns.pop().is_some() {
+                    self.write_in_line(1, writer)?;
+                    continue;
+                }
+                if has_element && self.settings.trailing_comma().applies(true) && !self.options.comma_first {
+                    writer.write_all(b",")?;
+                }
+                self.write_line_break(writer)?;
+                self.write_indents(self.written_unmatched_brackets.len(), writer)?;
+                self.write_in_line(1, writer)?;
+                continue;
+            }
+            if matches!(first.element_type, ElementType::Comment | ElementType::Marker) {
+                // the structure containing the commented value is expanded
+                self.write_comment_lines(writer)?;
+                continue;
+            }
+
+            // an object member consists of the key and its value
+            let value = if first.element_type == ElementType::ObjectKey { 1 } else { 0 };
+            if value >= self.elements.len() {
+                return Ok(())
+            }
+            let end = self.end_of_structure(value);
+            let last = end.unwrap_or(self.elements.len() - 1);
+            let line_start = match self.hanging_columns.last() {
+                Some(&column) => column,
+                None if self.written_unmatched_brackets.is_empty() => self.options.start_column,
+                None => self.display_length(self.settings.line_prefix)
+                    + self.options.indent_impact * self.written_unmatched_brackets.len(),
+            };
+            if let Some((width, column)) = self.filled_line {
+                // the numbers of a packed byte array are written in one line while they fit
+                let last = match self.elements.get(1) {
+                    Some(next) => next.element_type == ElementType::CloseBracket,
+                    None => return Ok(()),
+                };
+                let length = self.elements[0].length + if last { 0 } else { 1 };
+                let has_element = self.written_unmatched_brackets.last() == Some(&true);
+                let column = if has_element && column + 1 + length <= width {
+                    writer.write_all(b", ")?;
+                    column + 1 + length
+                } else {
+                    self.start_line(writer)?;
+                    line_start + length
+                };
+                self.filled_line = Some((width, column));
+                self.write_in_line(1, writer)?;
+                continue;
+            }
+            // an empty structure is written in one line whatever its decision is, a structure
+            // without elements yet may still be empty
+            let has_elements = |index: usize| self.elements.get(index + 1)
+                .is_some_and(|element| element.element_type != ElementType::CloseBracket);
+            let empty = self.elements[value].element_type == ElementType::OpenBracket && !has_elements(value);
+            let decision = self.elements[value].decision;
+            let fits = empty || match decision {
+                Decision::Auto => line_start + self.line_length(0..last + 1) <= self.options.line_break_with,
+                Decision::Expand => false,
+                Decision::Compact => true,
+                Decision::Width(width) => self.line_length(value..last + 1) <= width,
+                Decision::Fill(width) => line_start + self.line_length(0..last + 1) <= width,
+            };
+            // a structure inside which has to be expanded prevents writing the value in one line
+            let fits = fits && !(value + 1..=last).any(|index| {
+                let element = &self.elements[index];
+                let expanded = match element.decision {
+                    Decision::Expand => true,
+                    Decision::Width(width) => {
+                        let end = self.end_of_structure(index).unwrap_or(self.elements.len() - 1);
+                        self.line_length(index..end + 1) > width
+                    }
+                    _ => false,
+                };
+                element.element_type == ElementType::OpenBracket && expanded && has_elements(index)
             });
 
-            //The next Element will start at the current end of the buffer
-            self.current_element_end = self.cached_data.len();
+            match end {
+                Some(end) if fits || self.elements[value].element_type != ElementType::OpenBracket => {
+                    self.start_line(writer)?;
+                    self.write_in_line(end + 1, writer)?;
+                }
+                // the structure may still fit
+                None if fits => return Ok(()),
+                _ => {
+                    // the structure doesn't fit => every element gets its own line
+                    self.start_line(writer)?;
+                    if self.options.hanging_indent {
+                        let column = line_start + self.line_length(0..value + 1);
+                        self.write_in_line(value + 1, writer)?;
+                        self.hanging_columns.push(column);
+                    } else if self.options.comma_first && value > 0 {
+                        // the bracket starts its own line below the key
+                        self.write_in_line(value, writer)?;
+                        let key_value = self.separator(ElementType::ObjectKey, ElementType::OpenBracket);
+                        writer.write_all(key_value.trim_end().as_bytes())?;
+                        self.write_line_break(writer)?;
+                        self.write_indents(self.written_unmatched_brackets.len(), writer)?;
+                        self.write_in_line(1, writer)?;
+                    } else {
+                        self.write_in_line(value + 1, writer)?;
+                    }
+                    self.written_unmatched_brackets.push(false);
+                    if let Decision::Fill(width) = decision {
+                        self.filled_line = Some((width, 0));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_part(&mut self, data: &str) {
+        self.current_length += self.display_length(data);
+        self.cached_data.push_str(data);
+    }
 
-            if self.written_unmatched_brackets as isize + self.unwritten_unmatched_brackets == 0 {
-                // We are finished => write back all elements, they will fit in one line, if not
-                // Self::write_part would have written the the part which does##nt fit!
+    fn push_element(&mut self, data: &str, element_type: ElementType) {
+        self.write_part(data);
 
+        let decision = if element_type == ElementType::OpenBracket {
+            std::mem::replace(&mut self.decision, Decision::Auto)
+        } else {
+            Decision::Auto
+        };
+        self.elements.push(Element {
+            end: self.cached_data.len(),
+            length: self.current_length,
+            element_type,
+            decision,
+        });
+        self.current_length = 0;
+    }
+
+    // caches the comment of the current value
+    fn push_comment(&mut self) {
+        if self.settings.comments.is_empty() {
+            return;
+        }
+        if let Some(comment) = self.settings.comments.get(&self.path) {
+            self.push_element(&comment, ElementType::Comment);
+        }
+    }
+
+    fn apply<W: ?Sized + io::Write>(&mut self, writer: &mut W, event: Event) -> io::Result<()> {
+        self.settings.call_path_hook(&event, self.in_key, &self.path);
+        match event {
+            Event::Value(value) => self.write(&value, ElementType::Element, writer),
+            Event::Float(value, shortest) => {
+                let rendered = self.settings.float_formats.get(&self.path).render(value, &shortest);
+                self.write(&rendered, ElementType::Element, writer)
+            }
+            Event::String(raw) => {
+                let mut rendered = String::with_capacity(raw.len() + 2);
+                if self.in_key {
+                    self.settings.dialect.render_key(&raw, &self.escaping, &mut rendered);
+                    self.path.begin_member(raw);
+                    self.push_comment();
+                    self.write(&rendered, ElementType::ObjectKey, writer)
+                } else {
+                    self.settings.dialect.render_string(&raw, &self.escaping, &mut rendered);
+                    self.write(&rendered, ElementType::Element, writer)
+                }
+            }
+            Event::Bytes(bytes) => {
+                // an array whose numbers fill its lines if it doesn't fit in one line
+                self.decision = match self.decisions.open(&self.settings.rules, &self.path) {
+                    Decision::Auto => match self.settings.byte_arrays {
+                        ByteArrays::Packed(width) => Decision::Fill(width),
+                        _ => Decision::Auto,
+                    },
+                    decision => decision,
+                };
+                self.write("[", ElementType::OpenBracket, writer)?;
+                for byte in bytes {
+                    self.write(&byte.to_string(), ElementType::Element, writer)?;
+                }
+                self.decisions.close();
+                self.write("]", ElementType::CloseBracket, writer)
             }
+            Event::Comment(comment) => {
+                self.push_element(&comment, ElementType::Marker);
+                Ok(())
+            }
+            Event::SkipElements(count) => {
+                self.path.skip_elements(count);
+                Ok(())
+            }
+            Event::BeginArray => {
+                self.decision = self.decisions.open(&self.settings.rules, &self.path);
+                self.path.begin_array();
+                self.write("[", ElementType::OpenBracket, writer)
+            }
+            Event::EndArray => {
+                self.path.end_array();
+                self.decisions.close();
+                self.write("]", ElementType::CloseBracket, writer)
+            }
+            Event::BeginObject => {
+                self.decision = self.decisions.open(&self.settings.rules, &self.path);
+                self.write("{", ElementType::OpenBracket, writer)
+            }
+            Event::EndObject => {
+                self.decisions.close();
+                self.write("}", ElementType::CloseBracket, writer)
+            }
+            Event::BeginArrayValue(_) => {
+                self.path.begin_element();
+                self.push_comment();
+                Ok(())
+            }
+            Event::BeginObjectKey(_) => {
+                self.in_key = true;
+                Ok(())
+            }
+            Event::EndObjectKey => {
+                self.in_key = false;
+                Ok(())
+            }
+            Event::EndArrayValue | Event::EndObjectValue => {
+                self.path.end_value();
+                Ok(())
+            }
+            Event::BeginObjectValue => Ok(()),
+        }
+    }
+
+    fn write<W: ?Sized + io::Write>(&mut self, data: &str, element_type: ElementType, writer: &mut W) -> io::Result<()> {
+        self.push_element(data, element_type);
+        self.write_back_overflowing_elements(writer)?;
+        let value_end = matches!(element_type, ElementType::Element | ElementType::CloseBracket);
+        if self.settings.final_newline && value_end && self.decisions.depth() == 0 {
+            writer.write_all(self.settings.line_ending.as_str().as_bytes())?;
         }
-        result
+        Ok(())
     }
 }
 
-impl<'a> Formatter for CompactPrettyFormatter<'a> {
+impl<'a, E: EscapePolicy> EventSource<'a> for CompactPrettyFormatter<'a, E> {
+    type Escaping = E;
+
+    fn parts(&mut self) -> (&Settings<'a>, &E, &mut Input) {
+        (&self.settings, &self.escaping, &mut self.input)
+    }
+    fn layout<W: ?Sized + io::Write>(&mut self, writer: &mut W, event: Event) -> io::Result<()> {
+        self.apply(writer, event)
+    }
+}
+
+impl<'a, E: EscapePolicy> Formatter for CompactPrettyFormatter<'a, E> {
     /// Writes a `null` value to the specified writer.
     #[inline]
     fn write_null<W>(&mut self, writer: &mut W) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        self.write("null", ElementType::Element, writer)
+        self.write_scalar(writer, "null")
     }
 
     /// Writes a `true` or `false` value to the specified writer.
@@ -266,7 +714,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         } else {
             "false"
         };
-        self.write(s, ElementType::Element, writer)
+        self.write_scalar(writer, s)
     }
 
     /// Writes an integer value like `-123` to the specified writer.
@@ -277,7 +725,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_scalar(writer, s)
     }
 
     /// Writes an integer value like `-123` to the specified writer.
@@ -288,7 +736,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_scalar(writer, s)
     }
 
     /// Writes an integer value like `-123` to the specified writer.
@@ -299,7 +747,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_scalar(writer, s)
     }
 
     /// Writes an integer value like `-123` to the specified writer.
@@ -310,7 +758,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_integer(writer, s, value.unsigned_abs() as u128)
     }
 
     /// Writes an integer value like `123` to the specified writer.
@@ -321,7 +769,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_scalar(writer, s)
     }
 
     /// Writes an integer value like `123` to the specified writer.
@@ -332,7 +780,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_scalar(writer, s)
     }
 
     /// Writes an integer value like `123` to the specified writer.
@@ -343,7 +791,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_scalar(writer, s)
     }
 
     /// Writes an integer value like `123` to the specified writer.
@@ -354,7 +802,29 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_integer(writer, s, value as u128)
+    }
+
+    /// Writes an integer value like `-123` to the specified writer.
+    #[inline]
+    fn write_i128<W>(&mut self, writer: &mut W, value: i128) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_integer(writer, s, value.unsigned_abs())
+    }
+
+    /// Writes an integer value like `123` to the specified writer.
+    #[inline]
+    fn write_u128<W>(&mut self, writer: &mut W, value: u128) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_integer(writer, s, value)
     }
 
     /// Writes a floating point value like `-31.26e+12` to the specified writer.
@@ -365,7 +835,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = ryu::Buffer::new();
         let s = buffer.format_finite(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_float(writer, value as f64, s)
     }
 
     /// Writes a floating point value like `-31.26e+12` to the specified writer.
@@ -376,7 +846,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
     {
         let mut buffer = ryu::Buffer::new();
         let s = buffer.format_finite(value);
-        self.write(s, ElementType::Element, writer)
+        self.write_float(writer, value, s)
     }
 
     /// Writes a number that has already been rendered to a string.
@@ -385,73 +855,48 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.write(value, ElementType::Element, writer)
+        self.write_scalar(writer, value)
     }
 
     /// Called before each series of `write_string_fragment` and
-    /// `write_char_escape`.  Writes a `"` to the specified writer.
+    /// `write_char_escape`.
     #[inline]
-    fn begin_string<W>(&mut self, writer: &mut W) -> io::Result<()>
+    fn begin_string<W>(&mut self, _writer: &mut W) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        self.start_string(writer)
+        self.input.string.begin();
+        Ok(())
     }
 
     /// Called after each series of `write_string_fragment` and
-    /// `write_char_escape`.  Writes a `"` to the specified writer.
+    /// `write_char_escape`.  Writes the quoted string to the specified writer.
     #[inline]
     fn end_string<W>(&mut self, writer: &mut W) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        self.end_string(writer)
-
+        self.finish_string(writer)
     }
 
-    /// Writes a string fragment that doesn't need any escaping to the
-    /// specified writer.
+    /// Collects a string fragment that doesn't need any escaping.
     #[inline]
-    fn write_string_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
+    fn write_string_fragment<W>(&mut self, _writer: &mut W, fragment: &str) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        self.write_part(fragment, writer)
+        self.input.string.push_str(fragment);
+        Ok(())
     }
 
-    /// Writes a character escape code to the specified writer.
+    /// Collects the character behind a character escape code.
     #[inline]
-    fn write_char_escape<W>(&mut self, writer: &mut W, char_escape: CharEscape) -> io::Result<()>
+    fn write_char_escape<W>(&mut self, _writer: &mut W, char_escape: CharEscape) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        use self::CharEscape::*;
-
-        let s = match char_escape {
-            Quote => "\\\"",
-            ReverseSolidus => "\\\\",
-            Solidus => "\\/",
-            Backspace => "\\b",
-            FormFeed => "\\f",
-            LineFeed => "\\n",
-            CarriageReturn => "\\r",
-            Tab => "\\t",
-            AsciiControl(byte) => {
-                todo!()
-                /*static HEX_DIGITS: [u8; 16] = *b"0123456789abcdef";
-                let bytes = &[
-                    b'\\',
-                    b'u',
-                    b'0',
-                    b'0',
-                    HEX_DIGITS[(byte >> 4) as usize],
-                    HEX_DIGITS[(byte & 0xF) as usize],
-                ];
-                return writer.write_all(bytes);*/
-            }
-        };
-
-        self.write_part(s, writer)
+        self.input.string.push_escape(char_escape);
+        Ok(())
     }
 
     /// Called before every array.  Writes a `[` to the specified
@@ -461,7 +906,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.write("[", ElementType::OpenBracket, writer)
+        self.event(writer, Event::BeginArray)
     }
 
     /// Called after every array.  Writes a `]` to the specified
@@ -471,19 +916,25 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.write("]", ElementType::CloseBracket, writer)
+        self.event(writer, Event::EndArray)
     }
 
     /// Called before every array value.  Writes a `,` if needed to
     /// the specified writer.
     #[inline]
-    fn begin_array_value<W>(&mut self, _: &mut W, _: bool) -> io::Result<()>
-        where W: ?Sized + io::Write, {Ok(())}
+    fn begin_array_value<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+        where W: ?Sized + io::Write,
+    {
+        self.event(writer, Event::BeginArrayValue(first))
+    }
 
     /// Called after every array value.
     #[inline]
-    fn end_array_value<W>(&mut self, _writer: &mut W) -> io::Result<()>
-        where W: ?Sized + io::Write, { Ok(()) }
+    fn end_array_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: ?Sized + io::Write,
+    {
+        self.event(writer, Event::EndArrayValue)
+    }
 
     /// Called before every object.  Writes a `{` to the specified
     /// writer.
@@ -492,7 +943,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.write("{", ElementType::OpenBracket, writer)
+        self.event(writer, Event::BeginObject)
     }
 
     /// Called after every object.  Writes a `}` to the specified
@@ -502,7 +953,7 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.write("[", ElementType::CloseBracket, writer)
+        self.event(writer, Event::EndObject)
     }
 
     /// Called before every object key.
@@ -511,38 +962,45 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.start_key(writer)
+        self.start_key();
+        self.event(writer, Event::BeginObjectKey(first))
     }
 
-    /// Called after every object key.  A `:` should be written to the
-    /// specified writer by either this method or
-    /// `begin_object_value`.
+    /// Called after every object key. The `:` is written as separator of key and value.
     #[inline]
     fn end_object_key<W>(&mut self, writer: &mut W) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        self.end_key(writer)
+        self.end_key();
+        self.event(writer, Event::EndObjectKey)
     }
 
-    /// Called before every object value.  A `:` should be written to
-    /// the specified writer by either this method or
-    /// `end_object_key`.
+    /// Called before every object value.
     #[inline]
     fn begin_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        writer.write_all(b":")
+        self.event(writer, Event::BeginObjectValue)
     }
 
     /// Called after every object value.
     #[inline]
-    fn end_object_value<W>(&mut self, _writer: &mut W) -> io::Result<()>
+    fn end_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
         where
             W: ?Sized + io::Write,
     {
-        Ok(())
+        self.event(writer, Event::EndObjectValue)
+    }
+
+    /// Writes a byte array, as configured by `with_byte_arrays`.
+    #[inline]
+    fn write_byte_array<W>(&mut self, writer: &mut W, value: &[u8]) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.write_bytes(writer, value)
     }
 
     /// Writes a raw JSON fragment that doesn't need any escaping to the
@@ -552,6 +1010,60 @@ impl<'a> Formatter for CompactPrettyFormatter<'a> {
         where
             W: ?Sized + io::Write,
     {
-        writer.write_all(fragment.as_bytes())
+        self.write_scalar(writer, fragment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{CompactPrettyFormatter, Layout, LayoutRules};
+    use serde_json::json;
+
+    #[test]
+    fn hanging_indent() {
+        let value = json!({"name": "a", "list": [1, 2, 3], "nested": {"x": [10, 20]}});
+        let formatter = CompactPrettyFormatter::new(16, "  ", true).with_hanging_indent(true);
+        assert_eq!(
+            format(formatter, &value),
+            "{\"list\": [1,\n          2,\n          3],\n \"name\": \"a\",\n \"nested\": {\"x\": [10,\n                  20]}}"
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn start_column_and_line_prefix() {
+        let value = json!({"a": [1, 2], "b": 3});
+        // fits in 24 columns, but not behind the start column
+        let formatter = CompactPrettyFormatter::new(24, "  ", true);
+        assert_eq!(format(formatter, &value), "{\"a\": [1, 2], \"b\": 3}");
+        let formatter = CompactPrettyFormatter::new(24, "  ", true).with_start_column(8);
+        assert_eq!(format(formatter, &value), "{\n  \"a\": [1, 2],\n  \"b\": 3\n}");
+
+        // the prefix counts towards the width of the following lines
+        let formatter = CompactPrettyFormatter::new(15, "  ", true).with_line_prefix("/// ");
+        assert_eq!(format(formatter, &value), "{\n///   \"a\": [\n///     1,\n///     2\n///   ],\n///   \"b\": 3\n/// }");
+    }
+
+    #[test]
+    fn empty_structures_in_one_line() {
+        let value = json!({"d": {}, "e": []});
+        let rules = LayoutRules::new()
+            .with_rule("$.d".parse().unwrap(), Layout::Fold(1))
+            .with_rule("$.e".parse().unwrap(), Layout::Expand);
+        let formatter = CompactPrettyFormatter::new(80, "  ", true).with_layout_rules(rules);
+        assert_eq!(format(formatter, &value), "{\"d\": {}, \"e\": []}");
+
+        let formatter = CompactPrettyFormatter::new(5, "  ", true);
+        assert_eq!(format(formatter, &value), "{\n  \"d\": {},\n  \"e\": []\n}");
+    }
+    #[test]
+    fn width_in_display_columns() {
+        // 23 bytes, but 17 columns with the wide characters taking two
+        let value = json!(["日本語", "äöü"]);
+        assert_eq!(format(CompactPrettyFormatter::new(17, "  ", true), &value), r#"["日本語", "äöü"]"#);
+        assert_eq!(
+            format(CompactPrettyFormatter::new(16, "  ", true), &value),
+            "[\n  \"日本語\",\n  \"äöü\"\n]"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use crate::dialect::Dialect;
+use crate::escape::EscapePolicy;
+use crate::path::Path;
+use crate::selector::Selector;
+
+/// `Number.MAX_SAFE_INTEGER`, the largest integer JavaScript numbers represent exactly.
+pub const MAX_SAFE_INTEGER: u128 = (1 << 53) - 1;
+
+/// How 64 and 128 bit integers beyond a limit are written, by default the limit is
+/// [`MAX_SAFE_INTEGER`] so the values would lose precision when parsed by JavaScript.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub enum BigIntegers {
+    /// Write them as numbers like every other integer.
+    #[default]
+    Number,
+    /// Write them as strings, like `"9007199254740993"`.
+    Quote,
+    /// Write them as numbers followed by a `/* */` comment, which is not valid plain JSON.
+    Comment,
+}
+
+impl BigIntegers {
+    /// Renders the integer `digits` whose absolute value is `magnitude`, None if it is written
+    /// as is.
+    pub(crate) fn render(
+        &self,
+        digits: &str,
+        magnitude: u128,
+        limit: u128,
+        dialect: &Dialect,
+        escaping: &impl EscapePolicy,
+    ) -> Option<String> {
+        if magnitude <= limit {
+            return None;
+        }
+        match self {
+            BigIntegers::Number => None,
+            BigIntegers::Quote => {
+                let mut rendered = String::with_capacity(digits.len() + 2);
+                dialect.render_string(digits, escaping, &mut rendered);
+                Some(rendered)
+            }
+            BigIntegers::Comment => Some(format!("{} /* exceeds the safe integer range */", digits)),
+        }
+    }
+}
+
+/// How finite floats are written.
+///
+/// The default is the shortest representation which parses back to the same value, like
+/// `0.30000000000000004`, `1.0` or `1e16`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FloatFormat {
+    digits: FloatDigits,
+    exponent: bool,
+    trim_integral: bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+enum FloatDigits {
+    #[default]
+    Shortest,
+    Decimals(usize),
+    Significant(usize),
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FloatFormat {
+    pub fn new() -> Self {
+        FloatFormat {
+            digits: FloatDigits::Shortest,
+            exponent: true,
+            trim_integral: false,
+        }
+    }
+    /// Writes exactly `decimals` digits after the decimal point, like `3.140`.
+    pub fn fixed_decimals(mut self, decimals: usize) -> Self {
+        self.digits = FloatDigits::Decimals(decimals);
+        self
+    }
+    /// Rounds to `digits` significant digits, like `3.142` for 4 digits.
+    pub fn significant_digits(mut self, digits: usize) -> Self {
+        self.digits = FloatDigits::Significant(digits.max(1));
+        self
+    }
+    /// Never uses exponent notation, `1e16` is written as `10000000000000000.0`.
+    pub fn never_exponent(mut self) -> Self {
+        self.exponent = false;
+        self
+    }
+    /// Drops a fractional part which consists of zeros only, `1.0` is written as `1`.
+    pub fn trim_integral_zero(mut self) -> Self {
+        self.trim_integral = true;
+        self
+    }
+
+    /// Renders the finite `value`, `shortest` is its shortest representation.
+    pub(crate) fn render(&self, value: f64, shortest: &str) -> String {
+        let mut rendered = match self.digits {
+            FloatDigits::Shortest => shortest.to_string(),
+            FloatDigits::Decimals(decimals) => format!("{:.*}", decimals, value),
+            FloatDigits::Significant(digits) => {
+                let scientific = format!("{:.*e}", digits - 1, value);
+                // the same range in which the shortest representation has no exponent
+                match exponent_of(&scientific) {
+                    Some(exponent) if (-5..16).contains(&exponent) => expand_exponent(&scientific),
+                    _ => scientific,
+                }
+            }
+        };
+        if !self.exponent {
+            rendered = expand_exponent(&rendered);
+        }
+        if self.trim_integral {
+            rendered = trim_integral_zero(&rendered);
+        }
+        rendered
+    }
+}
+
+/// The float formats of the values, by default and for selected paths.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FloatFormats {
+    pub default: FloatFormat,
+    // the first matching rule wins
+    pub rules: Vec<(Selector, FloatFormat)>,
+}
+
+impl FloatFormats {
+    pub fn get(&self, path: &Path) -> FloatFormat {
+        self.rules.iter()
+            .find(|(selector, _)| selector.matches(path))
+            .map_or(self.default, |(_, format)| *format)
+    }
+}
+
+fn exponent_of(number: &str) -> Option<i32> {
+    number.split_once(['e', 'E']).and_then(|(_, exponent)| exponent.parse().ok())
+}
+
+/// Writes a number in exponent notation as plain decimal, integral values keep a `.0`.
+fn expand_exponent(number: &str) -> String {
+    let (mantissa, exponent) = match (number.split_once(['e', 'E']), exponent_of(number)) {
+        (Some((mantissa, _)), Some(exponent)) => (mantissa, exponent),
+        _ => return number.to_string(),
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let (integral, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", integral, fraction);
+    // position of the decimal point in digits
+    let point = integral.len() as i64 + exponent as i64;
+    let mut expanded = String::from(sign);
+    if point <= 0 {
+        expanded.push_str("0.");
+        expanded.push_str(&"0".repeat(-point as usize));
+        expanded.push_str(&digits);
+    } else if point as usize >= digits.len() {
+        expanded.push_str(&digits);
+        expanded.push_str(&"0".repeat(point as usize - digits.len()));
+        expanded.push_str(".0");
+    } else {
+        let (integral, fraction) = digits.split_at(point as usize);
+        expanded.push_str(integral);
+        expanded.push('.');
+        expanded.push_str(fraction);
+    }
+    expanded
+}
+
+/// Removes a fractional part consisting of zeros only, also in front of an exponent.
+fn trim_integral_zero(number: &str) -> String {
+    let (mantissa, exponent) = match number.find(['e', 'E']) {
+        Some(index) => number.split_at(index),
+        None => (number, ""),
+    };
+    match mantissa.split_once('.') {
+        Some((integral, fraction)) if fraction.bytes().all(|digit| digit == b'0') => {
+            format!("{}{}", integral, exponent)
+        }
+        _ => number.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{
+        BigIntegers, CompactPrettyFormatter, Dialect, FloatFormat, FoldPolicy, OpenStructures, Preview, Selector,
+        MAX_SAFE_INTEGER,
+    };
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn selector(selector: &str) -> Selector {
+        selector.parse().unwrap()
+    }
+
+    #[test]
+    fn big_integers_beyond_the_limit() {
+        let value = json!([9007199254740991u64, 9007199254740992u64, -9007199254740992i64, 1.5]);
+        let formatter = OpenStructures::new("  ", 0).with_big_integers(BigIntegers::Quote, MAX_SAFE_INTEGER);
+        assert_eq!(
+            format(formatter, &value),
+            r#"[9007199254740991, "9007199254740992", "-9007199254740992", 1.5]"#
+        );
+        let formatter = CompactPrettyFormatter::new(200, "  ", true)
+            .with_big_integers(BigIntegers::Comment, MAX_SAFE_INTEGER);
+        assert_eq!(
+            format(formatter, &value),
+            "[9007199254740991, 9007199254740992 /* exceeds the safe integer range */, \
+             -9007199254740992 /* exceeds the safe integer range */, 1.5]"
+        );
+        let formatter = OpenStructures::new("  ", 0).with_big_integers(BigIntegers::Number, 0);
+        assert_eq!(format(formatter, &value), "[9007199254740991, 9007199254740992, -9007199254740992, 1.5]");
+    }
+
+    #[test]
+    fn big_integers_with_custom_limit_and_dialect() {
+        let value: BTreeMap<_, u128> = vec![("small", 100), ("big", 1000), ("u128", u128::MAX)].into_iter().collect();
+        let formatter = OpenStructures::new("  ", 0)
+            .with_dialect(Dialect::Json5 { single_quotes: true })
+            .with_big_integers(BigIntegers::Quote, 999);
+        assert_eq!(
+            format(formatter, &value),
+            "{big: '1000', small: 100, u128: '340282366920938463463374607431768211455'}"
+        );
+    }
+
+    #[test]
+    fn float_format_in_lookahead() {
+        let value = json!({"d": [0.5, 1e-7, 2.0]});
+        let formatter = OpenStructures::new("  ", 0)
+            .with_fold_policy(FoldPolicy::new().expand_above_elements(10))
+            .with_float_format_at(selector("$.d[1]"), FloatFormat::new().fixed_decimals(1));
+        assert_eq!(format(formatter, &value), r#"{"d": [0.5, 0.0, 2.0]}"#);
+    }
+
+    #[test]
+    fn float_format_in_lookahead_decides_width() {
+        let value = json!([1.5, 2.5]);
+        let formatter = OpenStructures::new("  ", 0)
+            .with_fold_policy(FoldPolicy::new().expand_above_length(12))
+            .with_float_format_at(selector("$[*]"), FloatFormat::new().fixed_decimals(4));
+        assert_eq!(format(formatter, &value), "[\n  1.5000,\n  2.5000\n]");
+    }
+
+    #[test]
+    fn float_format_in_elided_tail() {
+        let value = json!({"d": [1.0, 2.0, 3.0, 4.25]});
+        let formatter = CompactPrettyFormatter::new(80, "  ", true)
+            .with_preview(Preview::new().elide_arrays(1, 1))
+            .with_float_format_at(selector("$.d[3]"), FloatFormat::new().fixed_decimals(3));
+        assert_eq!(format(formatter, &value), r#"{"d": [1.0, "… 2 more …", 4.250]}"#);
+    }
+}
@@ -1,43 +1,917 @@
-use serde_json::ser::Formatter;
+use crate::bytes::{pack, ByteArrays, PackedLines};
+use crate::comments::{block_comment, line_comments, Comments};
+use crate::common::{settings_builders, EventSource, Input, Settings};
+use crate::dialect::Dialect;
+use crate::escape::{EscapePolicy, Minimal};
+use crate::event::Event;
+use crate::numbers::{BigIntegers, FloatFormat};
+use crate::preview::{display_width, Preview};
+use crate::projection::Projection;
+use crate::redaction::Redaction;
+use crate::selector::Selector;
+use crate::layout::{Decision, Decisions, FoldPolicy, LayoutRules, LineEnding, Separators, TrailingComma};
+use crate::path::Path;
+use serde_json::ser::{CharEscape, Formatter};
 use std::io;
 use std::io::Write;
 
-pub struct OpenStructures<'a>{
+// The events of a structure whose layout is still undecided
+struct Lookahead {
+    events: Vec<Event>,
+    // the path of the last recorded event
+    path: Path,
+    // the decisions for the opened structures, continued in the recorded events
+    decisions: Decisions,
+    // the depth of the structure containing the recorded one
+    depth: u32,
+    // for every unclosed structure in events when it has to be expanded
+    limits: Vec<Limits>,
+    // length of the events written in one line
+    length: usize,
+    // a recorded structure has to be expanded, the ones containing it too
+    expand: bool,
+}
+
+// The conditions under which a structure has to be expanded
+#[derive(Default)]
+struct Limits {
+    // the length of the lookahead before the structure was opened
+    start: usize,
+    // count of elements of the structure
+    elements: usize,
+    // the structure is expanded if it gets more elements than this
+    max_elements: Option<usize>,
+    // the structure is expanded if it gets longer than this
+    width: Option<usize>,
+    // the structure is expanded if it gets more than one element or an element which isn't a scalar
+    single_scalar: bool,
+}
+
+impl Limits {
+    fn is_unlimited(&self) -> bool {
+        self.max_elements.is_none() && self.width.is_none() && !self.single_scalar
+    }
+    fn exceeded(&self, length: usize) -> bool {
+        let exceeds = |value: usize, limit: Option<usize>| limit.is_some_and(|limit| value > limit);
+        exceeds(self.elements, self.max_elements)
+            || exceeds(length - self.start, self.width)
+            || (self.single_scalar && self.elements > 1)
+    }
+}
+
+impl Lookahead {
+    /// Starts to record the structure opened by `event` at `path` in a structure at `depth`.
+    fn new(event: Event, mut path: Path, decisions: Decisions, depth: u32, limits: Limits) -> Self {
+        if let Event::BeginArray = event {
+            path.begin_array();
+        }
+        Lookahead {
+            events: vec![event],
+            path,
+            decisions,
+            depth,
+            limits: vec![limits],
+            length: 1,
+            expand: false,
+        }
+    }
+    fn record(&mut self, event: Event, settings: &Settings, options: &Options, escaping: &impl EscapePolicy) {
+        let separators = &options.folded_separators;
+        self.length += match &event {
+            Event::Value(value) => value.len(),
+            Event::Float(value, shortest) => settings.float_formats.get(&self.path).render(*value, shortest).len(),
+            Event::Bytes(bytes) => pack(bytes, 0, None).len(),
+            Event::String(raw) => {
+                let mut rendered = String::new();
+                if let Some(Event::BeginObjectKey(_)) = self.events.last() {
+                    settings.dialect.render_key(raw, escaping, &mut rendered);
+                    self.path.begin_member(raw.clone());
+                    rendered.len() + self.comment_length(settings)
+                } else {
+                    settings.dialect.render_string(raw, escaping, &mut rendered);
+                    rendered.len()
+                }
+            }
+            Event::BeginArray | Event::BeginObject => {
+                // a structure which can't be written in one line can't be in a folded one either
+                let decision = self.decisions.open(&settings.rules, &self.path);
+                let depth = self.depth + self.limits.len() as u32 + 1;
+                let limits = options.limits(decision, depth).unwrap_or(Limits {
+                    max_elements: Some(0),
+                    ..Limits::default()
+                });
+                if self.limits.last().is_some_and(|parent| parent.single_scalar) {
+                    self.expand = true;
+                }
+                self.limits.push(Limits { start: self.length, ..limits });
+                if let Event::BeginArray = event {
+                    self.path.begin_array();
+                }
+                1
+            }
+            Event::EndArray | Event::EndObject => {
+                if let Event::EndArray = event {
+                    self.path.end_array();
+                }
+                self.decisions.close();
+                1
+            }
+            Event::BeginArrayValue(first) | Event::BeginObjectKey(first) => {
+                let comment = if let Event::BeginArrayValue(_) = event {
+                    self.path.begin_element();
+                    self.comment_length(settings)
+                } else {
+                    0
+                };
+                if let Some(limits) = self.limits.last_mut() {
+                    limits.elements += 1;
+                }
+                // the padding is counted twice for the closing bracket
+                comment + if *first { 2 * separators.padding.len() } else { separators.item.len() }
+            }
+            Event::BeginObjectValue => separators.key_value.len(),
+            Event::Comment(comment) => 1 + block_comment(comment).len(),
+            Event::SkipElements(count) => {
+                self.path.skip_elements(*count);
+                0
+            }
+            Event::EndArrayValue | Event::EndObjectValue => {
+                self.path.end_value();
+                0
+            }
+            Event::EndObjectKey => 0,
+        };
+        let length = self.length;
+        self.expand |= self.limits.iter().any(|limits| limits.exceeded(length));
+        if let Event::EndArray | Event::EndObject = event {
+            self.limits.pop();
+        }
+        self.events.push(event);
+    }
+    /// The length of the comment of the value at the current path, which is appended to the value
+    /// in one line.
+    fn comment_length(&self, settings: &Settings) -> usize {
+        if settings.comments.is_empty() {
+            return 0;
+        }
+        settings.comments.get(&self.path).map_or(0, |comment| 1 + block_comment(&comment).len())
+    }
+    /// Some(true) if the structure has to be expanded, Some(false) if it is complete and fits in
+    /// one line, None if that isn't known yet
+    fn decision(&self) -> Option<bool> {
+        if self.expand {
+            Some(true)
+        } else if self.limits.is_empty() {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+// the options only this formatter has, the shared ones are in the Settings
+struct Options<'a> {
     indent: &'a str,
-    fold_after: u32,
+    expanded_separators: Separators<'a>,
+    folded_separators: Separators<'a>,
+    fold_policy: FoldPolicy,
+    // keep structures containing only one scalar in one line
+    single_scalars_inline: bool,
+    comma_first: bool,
+    closing_brackets_inline: bool,
+}
+
+impl<'a> Options<'a> {
+    /// The conditions to expand a structure with `decision` at `depth`, None if it is expanded
+    /// anyway.
+    fn limits(&self, decision: Decision, depth: u32) -> Option<Limits> {
+        match decision {
+            Decision::Expand => None,
+            Decision::Compact => Some(Limits::default()),
+            Decision::Width(width) | Decision::Fill(width) => Some(Limits { width: Some(width), ..Limits::default() }),
+            Decision::Auto if self.fold_policy.expands_depth(depth) => {
+                if self.single_scalars_inline {
+                    Some(Limits { single_scalar: true, ..Limits::default() })
+                } else {
+                    None
+                }
+            }
+            Decision::Auto => {
+                let (max_elements, width) = self.fold_policy.content_limits();
+                Some(Limits { max_elements, width, ..Limits::default() })
+            }
+        }
+    }
+}
+
+pub struct OpenStructures<'a, E = Minimal>{
+    settings: Settings<'a>,
+    options: Options<'a>,
+    escaping: E,
+    input: Input,
     open: u32,
+    // for every opened structure if its elements are written in own lines
+    expanded: Vec<bool>,
+    decisions: Decisions,
+    lookahead: Option<Lookahead>,
+    // a comment for the next value in the structure at this depth
+    pending_comment: Option<(u32, String)>,
+    // the depth of the outermost structure which was folded because of its content,
+    // everything inside of it is folded too
+    folded_from: Option<u32>,
+    // true if the innermost opened structure already contains a value
+    has_value: bool,
+    // the key value separator is written once the layout of the value is known
+    pending_key_value: bool,
+    // the display width of the last written object key
+    key_width: usize,
+    // with comma first the opening bracket of an expanded object member is written once it is
+    // known if the structure is empty, an empty one stays in the line of the key
+    pending_bracket: Option<&'static [u8]>,
+    in_key: bool,
+    path: Path,
 }
 
 impl<'a> OpenStructures<'a> {
     pub fn new(indent: &'a str, fold_after: u32) -> Self {
-        OpenStructures {
+        let options = Options {
             indent,
-            fold_after,
-            open: 0
+            expanded_separators: Separators::default(),
+            folded_separators: Separators::default(),
+            fold_policy: FoldPolicy::new().expand_to_depth(fold_after),
+            single_scalars_inline: false,
+            comma_first: false,
+            closing_brackets_inline: false,
+        };
+        OpenStructures::from_settings(Settings::new(), options, Minimal)
+    }
+}
+
+impl<'a, E: EscapePolicy> OpenStructures<'a, E> {
+    fn from_settings(settings: Settings<'a>, options: Options<'a>, escaping: E) -> Self {
+        OpenStructures {
+            settings,
+            options,
+            escaping,
+            input: Input::default(),
+            open: 0,
+            expanded: vec![],
+            decisions: Decisions::default(),
+            lookahead: None,
+            pending_comment: None,
+            folded_from: None,
+            has_value: false,
+            pending_key_value: false,
+            key_width: 0,
+            pending_bracket: None,
+            in_key: false,
+            path: Path::default(),
         }
     }
+    settings_builders!(OpenStructures);
+    /// Sets the separators used in structures which are expanded over multiple lines.
+    pub fn with_expanded_separators(mut self, separators: Separators<'a>) -> Self {
+        self.options.expanded_separators = separators;
+        self
+    }
+    /// Sets the separators used in structures which are folded into one line.
+    pub fn with_folded_separators(mut self, separators: Separators<'a>) -> Self {
+        self.options.folded_separators = separators;
+        self
+    }
+    /// Sets which structures are expanded, replaces `fold_after`.
+    pub fn with_fold_policy(mut self, fold_policy: FoldPolicy) -> Self {
+        self.options.fold_policy = fold_policy;
+        self
+    }
+    /// Keeps structures which would be expanded but only contain one scalar, like `[1]` or
+    /// `{"a": 1}`, in one line.
+    pub fn with_single_scalars_inline(mut self, inline: bool) -> Self {
+        self.options.single_scalars_inline = inline;
+        self
+    }
+    /// Writes expanded structures with leading commas, every element starts its line with the
+    /// item separator:
+    /// ```text
+    /// [ 1
+    /// , 2
+    /// ]
+    /// ```
+    /// Trailing commas are never written in expanded structures of this style.
+    pub fn with_comma_first(mut self, comma_first: bool) -> Self {
+        self.options.comma_first = comma_first;
+        self
+    }
+    /// Appends the closing brackets of expanded structures to the line of their last element
+    /// instead of writing them in an own line, like `"c": 3}]`.
+    /// Trailing commas are never written in expanded structures of this style.
+    pub fn with_closing_brackets_inline(mut self, inline: bool) -> Self {
+        self.options.closing_brackets_inline = inline;
+        self
+    }
     /// provides the needed amount of indents basend on Self::open
     /// In opening structure methods this should get called after increasing open
     /// In closing structure methods this should get called before decreasing open
     fn print_indents(&self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
         for _ in 0..self.open {
-            writer.write(self.indent.as_bytes())?;
+            writer.write_all(self.options.indent.as_bytes())?;
         }
         Ok(())
     }
     fn print_indents_below(&self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
         for _ in 0..(self.open-1) {
-            writer.write(self.indent.as_bytes())?;
+            writer.write_all(self.options.indent.as_bytes())?;
+        }
+        Ok(())
+    }
+    /// the indents of the elements of an expanded structure, with comma first they are aligned
+    /// to the first element written behind the opening bracket
+    fn print_element_indents(&self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        if self.options.comma_first {
+            self.print_indents_below(writer)?;
+            self.print_comma_first_fill(writer, 0)
+        } else {
+            self.print_indents(writer)
+        }
+    }
+    /// fills the space behind a bracket or leading comma of `written` characters up to the width
+    /// of one indent
+    fn print_comma_first_fill(&self, writer: &mut (impl Write + ?Sized), written: usize) -> io::Result<()> {
+        let fill = self.options.indent.len().saturating_sub(written).max(1);
+        for _ in 0..fill {
+            writer.write_all(b" ")?;
         }
         Ok(())
     }
     fn is_open(&self) -> bool {
-        self.open <= self.fold_after
+        self.expanded.last().copied().unwrap_or(false)
+    }
+    fn separators(&self) -> &Separators<'a> {
+        if self.is_open() {
+            &self.options.expanded_separators
+        } else {
+            &self.options.folded_separators
+        }
+    }
+    /// The pending comment if it was given in the innermost structure.
+    fn take_pending_comment(&mut self) -> Option<String> {
+        match &self.pending_comment {
+            Some((open, _)) if *open == self.open => self.pending_comment.take().map(|(_, comment)| comment),
+            _ => None,
+        }
+    }
+    /// The comment of the current value, a pending comment in front of the configured one.
+    fn take_comment(&mut self) -> Option<String> {
+        let pending = self.take_pending_comment();
+        let configured = if self.settings.comments.is_empty() {
+            None
+        } else {
+            self.settings.comments.get(&self.path)
+        };
+        match (pending, configured) {
+            (Some(pending), Some(configured)) => Some(format!("{}\n{}", pending, configured)),
+            (pending, configured) => pending.or(configured),
+        }
+    }
+    /// In expanded structures writes the comment of the current value in own lines, in single
+    /// line structures only a pending comment in front of the value.
+    /// Should get called after the indents of the value are written.
+    fn write_leading_comment(&mut self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        if !self.is_open() {
+            if let Some(comment) = self.take_pending_comment() {
+                writer.write_all(block_comment(&comment).as_bytes())?;
+                writer.write_all(b" ")?;
+            }
+            return Ok(());
+        }
+        if let Some(comment) = self.take_comment() {
+            for line in line_comments(&comment) {
+                writer.write_all(line.as_bytes())?;
+                self.write_line_break(writer)?;
+                self.print_element_indents(writer)?;
+            }
+        }
+        Ok(())
+    }
+    /// In single line structures appends the comment of the current value.
+    fn write_trailing_comment(&mut self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        if self.is_open() {
+            return Ok(());
+        }
+        if let Some(comment) = self.take_comment() {
+            writer.write_all(b" ")?;
+            writer.write_all(block_comment(&comment).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the event, or records it if the layout of the current structure isn't decided yet.
+    fn decide(&mut self, writer: &mut (impl Write + ?Sized), event: Event) -> io::Result<()> {
+        match &mut self.lookahead {
+            Some(lookahead) => {
+                lookahead.record(event, &self.settings, &self.options, &self.escaping);
+                if let Some(expanded) = lookahead.decision() {
+                    let events = self.lookahead.take().unwrap().events;
+                    self.replay(writer, events, expanded)?;
+                }
+                Ok(())
+            }
+            None => self.apply(writer, event),
+        }
+    }
+    /// Writes recorded events, the first one opens the structure they were recorded for.
+    fn replay(&mut self, writer: &mut (impl Write + ?Sized), events: Vec<Event>, expanded: bool) -> io::Result<()> {
+        let mut events = events.into_iter();
+        let bracket: &'static [u8] = match events.next() {
+            Some(Event::BeginArray) => b"[",
+            _ => b"{",
+        };
+        self.open_structure(writer, bracket, expanded)?;
+        if !expanded && self.folded_from.is_none() {
+            self.folded_from = Some(self.open);
+        }
+        for event in events {
+            self.decide(writer, event)?;
+        }
+        Ok(())
+    }
+    fn apply(&mut self, writer: &mut (impl Write + ?Sized), event: Event) -> io::Result<()> {
+        self.settings.call_path_hook(&event, self.in_key, &self.path);
+        match event {
+            Event::Float(value, shortest) => {
+                let rendered = self.settings.float_formats.get(&self.path).render(value, &shortest);
+                self.write_value(writer, &rendered)
+            }
+            Event::Value(value) => self.write_value(writer, &value),
+            Event::Bytes(bytes) => {
+                // the lines of a packed array are only filled in an expanded structure
+                let width = match self.settings.byte_arrays {
+                    ByteArrays::Packed(width) => width,
+                    _ => usize::MAX,
+                };
+                let compact = self.decisions.open(&self.settings.rules, &self.path) == Decision::Compact;
+                self.decisions.close();
+                let lines = (!compact && (self.open == 0 || self.is_open())).then(|| PackedLines {
+                    line_break: self.settings.line_ending.as_str(),
+                    line_prefix: self.settings.line_prefix,
+                    indent: self.options.indent.repeat(self.open as usize + 1),
+                    close_indent: self.options.indent.repeat(self.open as usize),
+                    column: self.value_column(),
+                });
+                let rendered = pack(&bytes, width, lines);
+                self.write_value(writer, &rendered)
+            }
+            Event::String(raw) => {
+                self.write_pending_key_value(writer)?;
+                let mut rendered = String::with_capacity(raw.len() + 2);
+                if self.in_key {
+                    self.settings.dialect.render_key(&raw, &self.escaping, &mut rendered);
+                    self.key_width = display_width(&rendered);
+                    self.path.begin_member(raw);
+                    self.write_leading_comment(writer)?;
+                } else {
+                    self.settings.dialect.render_string(&raw, &self.escaping, &mut rendered);
+                }
+                writer.write_all(rendered.as_bytes())?;
+                if self.in_key {
+                    Ok(())
+                } else {
+                    self.end_value(writer)
+                }
+            }
+            Event::BeginArray | Event::BeginObject => {
+                let decision = self.decisions.open(&self.settings.rules, &self.path);
+                let expanded = match decision {
+                    _ if self.folded_from.is_some() => false,
+                    Decision::Compact => false,
+                    _ => match self.options.limits(decision, self.open + 1) {
+                        None => true,
+                        // without a lookahead only if nothing inside can be expanded
+                        Some(limits) if limits.is_unlimited() && !self.settings.rules.expand_below(&self.path) => false,
+                        Some(limits) => {
+                            let decisions = self.decisions.clone();
+                            self.lookahead = Some(Lookahead::new(event, self.path.clone(), decisions, self.open, limits));
+                            return Ok(());
+                        }
+                    },
+                };
+                let bracket: &'static [u8] = match event {
+                    Event::BeginArray => b"[",
+                    _ => b"{",
+                };
+                self.open_structure(writer, bracket, expanded)
+            }
+            Event::EndArray => {
+                self.path.end_array();
+                self.close(writer, b"]")
+            }
+            Event::EndObject => self.close(writer, b"}"),
+            Event::BeginArrayValue(first) => {
+                self.begin_value(writer, first)?;
+                self.path.begin_element();
+                self.write_leading_comment(writer)
+            }
+            Event::BeginObjectKey(first) => {
+                self.begin_value(writer, first)?;
+                self.in_key = true;
+                Ok(())
+            }
+            Event::EndObjectKey => {
+                self.in_key = false;
+                Ok(())
+            }
+            Event::BeginObjectValue => {
+                self.pending_key_value = true;
+                Ok(())
+            }
+            Event::Comment(comment) => {
+                self.pending_comment = Some((self.open, comment));
+                Ok(())
+            }
+            Event::SkipElements(count) => {
+                self.path.skip_elements(count);
+                Ok(())
+            }
+            Event::EndArrayValue | Event::EndObjectValue => {
+                self.write_trailing_comment(writer)?;
+                self.path.end_value();
+                Ok(())
+            }
+        }
+    }
+    /// The column at which the current value starts if it is the top level value or in an
+    /// expanded structure, a member value starts behind its key.
+    fn value_column(&self) -> usize {
+        if self.open == 0 {
+            return 0;
+        }
+        let key = if self.pending_key_value {
+            self.key_width + self.separators().key_value.len()
+        } else {
+            0
+        };
+        self.settings.line_prefix.len() + self.options.indent.len() * self.open as usize + key
+    }
+    fn write_value(&mut self, writer: &mut (impl Write + ?Sized), value: &str) -> io::Result<()> {
+        self.write_pending_key_value(writer)?;
+        writer.write_all(value.as_bytes())?;
+        self.end_value(writer)
+    }
+    /// Writes the separator between an object key and its value.
+    fn write_pending_key_value(&mut self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        if !self.pending_key_value {
+            return Ok(());
+        }
+        self.pending_key_value = false;
+        writer.write_all(self.separators().key_value.as_bytes())
+    }
+    fn open_structure(&mut self, writer: &mut (impl Write + ?Sized), bracket: &'static [u8], expanded: bool) -> io::Result<()> {
+        // with comma first a non empty expanded structure as member value starts in its own line
+        let defer = self.options.comma_first && expanded && self.pending_key_value && self.is_open();
+        if defer {
+            self.pending_key_value = false;
+        } else {
+            self.write_pending_key_value(writer)?;
+        }
+        self.open += 1;
+        self.expanded.push(expanded);
+        self.has_value = false;
+        if bracket == b"[" {
+            self.path.begin_array();
+        }
+        if defer {
+            self.pending_bracket = Some(bracket);
+            Ok(())
+        } else {
+            writer.write_all(bracket)
+        }
+    }
+    /// Writes the kept back opening bracket of the innermost structure, in the line of the key if
+    /// the structure is empty.
+    fn write_pending_bracket(&mut self, writer: &mut (impl Write + ?Sized), empty: bool) -> io::Result<()> {
+        let bracket = match self.pending_bracket.take() {
+            Some(bracket) => bracket,
+            None => return Ok(()),
+        };
+        let key_value = self.options.expanded_separators.key_value;
+        if empty {
+            writer.write_all(key_value.as_bytes())?;
+        } else {
+            writer.write_all(key_value.trim_end().as_bytes())?;
+            self.write_line_break(writer)?;
+            self.print_indents_below(writer)?;
+        }
+        writer.write_all(bracket)
+    }
+    /// Writes a `,` if needed and starts the line of the next array value or object member.
+    fn begin_value(&mut self, writer: &mut (impl Write + ?Sized), first: bool) -> io::Result<()> {
+        self.write_pending_bracket(writer, false)?;
+        let separators = self.separators();
+        if self.is_open() && self.options.comma_first {
+            if first {
+                self.print_comma_first_fill(writer, 1)?;
+            } else {
+                let item = separators.item.trim();
+                self.write_line_break(writer)?;
+                self.print_indents_below(writer)?;
+                writer.write_all(item.as_bytes())?;
+                self.print_comma_first_fill(writer, item.len())?;
+            }
+            self.has_value = true;
+            return Ok(());
+        }
+        if self.is_open() {
+            if !first {
+                writer.write_all(separators.item.trim_end().as_bytes())?;
+            }
+        } else if !first {
+            writer.write_all(separators.item.as_bytes())?;
+        } else {
+            writer.write_all(separators.padding.as_bytes())?;
+        }
+        if self.is_open() {
+            self.write_line_break(writer)?;
+            self.print_indents(writer)?;
+        }
+        self.has_value = true;
+        Ok(())
+    }
+    /// Writes a pending comment which has no value following it, true if one was written.
+    fn write_closing_comment(&mut self, writer: &mut (impl Write + ?Sized)) -> io::Result<bool> {
+        let comment = match self.take_pending_comment() {
+            Some(comment) => comment,
+            None => return Ok(false),
+        };
+        if self.is_open() {
+            for line in line_comments(&comment) {
+                self.write_line_break(writer)?;
+                self.print_element_indents(writer)?;
+                writer.write_all(line.as_bytes())?;
+            }
+        } else {
+            writer.write_all(b" ")?;
+            writer.write_all(block_comment(&comment).as_bytes())?;
+        }
+        Ok(true)
+    }
+    fn close(&mut self, writer: &mut (impl Write + ?Sized), bracket: &[u8]) -> io::Result<()> {
+        self.write_pending_bracket(writer, true)?;
+        let trailing_comma = self.settings.trailing_comma();
+        let inline = self.options.closing_brackets_inline && self.is_open();
+        let comma_first = self.options.comma_first && self.is_open();
+        if self.has_value && trailing_comma.applies(self.is_open()) && !comma_first && !inline {
+            writer.write_all(b",")?;
+        }
+        let commented = self.write_closing_comment(writer)?;
+        // empty structures are closed in the same line
+        if self.is_open() {
+            if self.has_value && (!inline || commented) {
+                self.write_line_break(writer)?;
+                self.print_indents_below(writer)?;
+            }
+        } else if self.has_value {
+            writer.write_all(self.options.folded_separators.padding.as_bytes())?;
+        }
+        writer.write_all(bracket)?;
+        if self.folded_from == Some(self.open) {
+            self.folded_from = None;
+        }
+        self.open -= 1;
+        self.expanded.pop();
+        self.decisions.close();
+        self.has_value = true;
+        self.end_value(writer)
+    }
+    /// Writes a line break followed by the line prefix.
+    fn write_line_break(&self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        writer.write_all(self.settings.line_ending.as_str().as_bytes())?;
+        writer.write_all(self.settings.line_prefix.as_bytes())
+    }
+    /// Called after every written value, ends the output after the top level value.
+    fn end_value(&self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        if self.settings.final_newline && self.open == 0 {
+            writer.write_all(self.settings.line_ending.as_str().as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, E: EscapePolicy> EventSource<'a> for OpenStructures<'a, E> {
+    type Escaping = E;
+
+    fn parts(&mut self) -> (&Settings<'a>, &E, &mut Input) {
+        (&self.settings, &self.escaping, &mut self.input)
+    }
+    fn layout<W: ?Sized + io::Write>(&mut self, writer: &mut W, event: Event) -> io::Result<()> {
+        self.decide(writer, event)
     }
 }
 
-impl<'a> Formatter for OpenStructures<'a> {
+impl<'a, E: EscapePolicy> Formatter for OpenStructures<'a, E> {
+    /// Writes a `null` value to the specified writer.
+    #[inline]
+    fn write_null<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.write_scalar(writer, "null")
+    }
+
+    /// Writes a `true` or `false` value to the specified writer.
+    #[inline]
+    fn write_bool<W>(&mut self, writer: &mut W, value: bool) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let s = if value {
+            "true"
+        } else {
+            "false"
+        };
+        self.write_scalar(writer, s)
+    }
+
+    /// Writes an integer value like `-123` to the specified writer.
+    #[inline]
+    fn write_i8<W>(&mut self, writer: &mut W, value: i8) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_scalar(writer, s)
+    }
+
+    /// Writes an integer value like `-123` to the specified writer.
+    #[inline]
+    fn write_i16<W>(&mut self, writer: &mut W, value: i16) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_scalar(writer, s)
+    }
+
+    /// Writes an integer value like `-123` to the specified writer.
+    #[inline]
+    fn write_i32<W>(&mut self, writer: &mut W, value: i32) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_scalar(writer, s)
+    }
+
+    /// Writes an integer value like `-123` to the specified writer.
+    #[inline]
+    fn write_i64<W>(&mut self, writer: &mut W, value: i64) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_integer(writer, s, value.unsigned_abs() as u128)
+    }
+
+    /// Writes an integer value like `-123` to the specified writer.
+    #[inline]
+    fn write_i128<W>(&mut self, writer: &mut W, value: i128) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_integer(writer, s, value.unsigned_abs())
+    }
+
+    /// Writes an integer value like `123` to the specified writer.
+    #[inline]
+    fn write_u8<W>(&mut self, writer: &mut W, value: u8) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_scalar(writer, s)
+    }
+
+    /// Writes an integer value like `123` to the specified writer.
+    #[inline]
+    fn write_u16<W>(&mut self, writer: &mut W, value: u16) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_scalar(writer, s)
+    }
+
+    /// Writes an integer value like `123` to the specified writer.
+    #[inline]
+    fn write_u32<W>(&mut self, writer: &mut W, value: u32) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_scalar(writer, s)
+    }
+
+    /// Writes an integer value like `123` to the specified writer.
+    #[inline]
+    fn write_u64<W>(&mut self, writer: &mut W, value: u64) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_integer(writer, s, value as u128)
+    }
+
+    /// Writes an integer value like `123` to the specified writer.
+    #[inline]
+    fn write_u128<W>(&mut self, writer: &mut W, value: u128) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = itoa::Buffer::new();
+        let s = buffer.format(value);
+        self.write_integer(writer, s, value)
+    }
+
+    /// Writes a floating point value like `-31.26e+12` to the specified writer.
+    #[inline]
+    fn write_f32<W>(&mut self, writer: &mut W, value: f32) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = ryu::Buffer::new();
+        let s = buffer.format_finite(value);
+        self.write_float(writer, value as f64, s)
+    }
+
+    /// Writes a floating point value like `-31.26e+12` to the specified writer.
+    #[inline]
+    fn write_f64<W>(&mut self, writer: &mut W, value: f64) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        let mut buffer = ryu::Buffer::new();
+        let s = buffer.format_finite(value);
+        self.write_float(writer, value, s)
+    }
 
+    /// Writes a number that has already been rendered to a string.
+    #[inline]
+    fn write_number_str<W>(&mut self, writer: &mut W, value: &str) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.write_scalar(writer, value)
+    }
+
+    /// Called before each series of `write_string_fragment` and
+    /// `write_char_escape`.
+    #[inline]
+    fn begin_string<W>(&mut self, _writer: &mut W) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.input.string.begin();
+        Ok(())
+    }
+
+    /// Called after each series of `write_string_fragment` and
+    /// `write_char_escape`.  Writes the quoted string to the specified writer.
+    #[inline]
+    fn end_string<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.finish_string(writer)
+    }
+
+    /// Collects a string fragment that doesn't need any escaping.
+    #[inline]
+    fn write_string_fragment<W>(&mut self, _writer: &mut W, fragment: &str) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.input.string.push_str(fragment);
+        Ok(())
+    }
+
+    /// Collects the character behind a character escape code.
+    #[inline]
+    fn write_char_escape<W>(&mut self, _writer: &mut W, char_escape: CharEscape) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.input.string.push_escape(char_escape);
+        Ok(())
+    }
 
     /// Called before every array.  Writes a `[` to the specified
     /// writer.
@@ -46,8 +920,7 @@ impl<'a> Formatter for OpenStructures<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.open += 1;
-        writer.write_all(b"[")
+        self.event(writer, Event::BeginArray)
     }
 
     /// Called after every array.  Writes a `]` to the specified
@@ -57,13 +930,7 @@ impl<'a> Formatter for OpenStructures<'a> {
         where
             W: ?Sized + io::Write,
     {
-        if self.is_open() {
-            writer.write_all(b"\n")?;
-            self.print_indents_below(writer)?;
-        }
-        writer.write_all(b"]")?;
-        self.open -= 1;
-        Ok(())
+        self.event(writer, Event::EndArray)
     }
 
     /// Called before every array value.  Writes a `,` if needed to
@@ -73,14 +940,16 @@ impl<'a> Formatter for OpenStructures<'a> {
         where
             W: ?Sized + io::Write,
     {
-        if !first {
-            writer.write_all(b", ")?;
-        }
-        if self.is_open() {
-            writer.write(b"\n")?;
-            self.print_indents(writer)?;
-        }
-        Ok(())
+        self.event(writer, Event::BeginArrayValue(first))
+    }
+
+    /// Called after every array value.
+    #[inline]
+    fn end_array_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.event(writer, Event::EndArrayValue)
     }
 
     /// Called before every object.  Writes a `{` to the specified
@@ -90,8 +959,7 @@ impl<'a> Formatter for OpenStructures<'a> {
         where
             W: ?Sized + io::Write,
     {
-        self.open += 1;
-        writer.write_all(b"{")
+        self.event(writer, Event::BeginObject)
     }
 
     /// Called after every object.  Writes a `}` to the specified
@@ -101,13 +969,7 @@ impl<'a> Formatter for OpenStructures<'a> {
         where
             W: ?Sized + io::Write,
     {
-        if self.is_open() {
-            writer.write_all(b"\n")?;
-            self.print_indents_below(writer)?;
-        }
-        writer.write_all(b"}")?;
-        self.open -= 1;
-        Ok(())
+        self.event(writer, Event::EndObject)
     }
 
     /// Called before every object key.
@@ -116,14 +978,18 @@ impl<'a> Formatter for OpenStructures<'a> {
         where
             W: ?Sized + io::Write,
     {
-        if !first {
-            writer.write_all(b", ")?;
-        }
-        if self.is_open() {
-            writer.write(b"\n")?;
-            self.print_indents(writer)?;
-        }
-        Ok(())
+        self.input.started_key = true;
+        self.event(writer, Event::BeginObjectKey(first))
+    }
+
+    /// Called after every object key.
+    #[inline]
+    fn end_object_key<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.input.started_key = false;
+        self.event(writer, Event::EndObjectKey)
     }
 
     #[inline]
@@ -131,6 +997,139 @@ impl<'a> Formatter for OpenStructures<'a> {
         where
             W: ?Sized + io::Write,
     {
-        writer.write_all(b": ")
+        self.event(writer, Event::BeginObjectValue)
+    }
+
+    /// Called after every object value.
+    #[inline]
+    fn end_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.event(writer, Event::EndObjectValue)
+    }
+
+    /// Writes a byte array, as configured by `with_byte_arrays`.
+    #[inline]
+    fn write_byte_array<W>(&mut self, writer: &mut W, value: &[u8]) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.write_bytes(writer, value)
     }
-}
\ No newline at end of file
+
+    /// Writes a raw JSON fragment that doesn't need any escaping to the
+    /// specified writer.
+    #[inline]
+    fn write_raw_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
+        where
+            W: ?Sized + io::Write,
+    {
+        self.write_scalar(writer, fragment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{FoldPolicy, Layout, LayoutRules, OpenStructures, Separators, TrailingComma};
+    use serde_json::json;
+
+    #[test]
+    fn empty_structures_in_one_line() {
+        let value = json!({"a": [], "b": {}});
+        let formatter = OpenStructures::new("  ", 2);
+        assert_eq!(format(formatter, &value), "{\n  \"a\": [],\n  \"b\": {}\n}");
+        assert_eq!(format(OpenStructures::new("  ", 1), &json!([])), "[]");
+    }
+
+    #[test]
+    fn single_scalars_inline() {
+        let value = json!({"a": [1], "b": {"c": true}, "d": [1, 2], "e": [[1]]});
+        let formatter = OpenStructures::new("  ", 3).with_single_scalars_inline(true);
+        assert_eq!(
+            format(formatter, &value),
+            "{\n  \"a\": [1],\n  \"b\": {\"c\": true},\n  \"d\": [\n    1,\n    2\n  ],\n  \"e\": [\n    [1]\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn custom_separators() {
+        let value = json!({"a": [1, 2], "b": {"c": null}, "d": []});
+        let formatter = OpenStructures::new("  ", 1)
+            .with_expanded_separators(Separators { item: " ,  ", key_value: " = ", padding: " " })
+            .with_folded_separators(Separators { item: ",", key_value: ":", padding: " " });
+        assert_eq!(
+            format(formatter, &value),
+            "{\n  \"a\" = [ 1,2 ] ,\n  \"b\" = { \"c\":null } ,\n  \"d\" = []\n}"
+        );
+    }
+
+    #[test]
+    fn no_trailing_whitespace() {
+        let value = json!({"a": [1, {"b": 2}], "c": 3});
+        let formatter = OpenStructures::new("\t", 3)
+            .with_folded_separators(Separators { item: ", ", key_value: ": ", padding: " " });
+        let formatted = format(formatter, &value);
+        assert_eq!(formatted, "{\n\t\"a\": [\n\t\t1,\n\t\t{\n\t\t\t\"b\": 2\n\t\t}\n\t],\n\t\"c\": 3\n}");
+        assert!(formatted.lines().all(|line| line.trim_end() == line));
+    }
+
+    #[test]
+    fn closing_brackets_inline() {
+        let value = json!({"a": [1, {"b": 2}], "c": []});
+        let formatter = OpenStructures::new("  ", 3)
+            .with_closing_brackets_inline(true)
+            .with_trailing_comma(TrailingComma::Always);
+        assert_eq!(
+            format(formatter, &value),
+            "{\n  \"a\": [\n    1,\n    {\n      \"b\": 2}],\n  \"c\": []}"
+        );
+    }
+
+    #[test]
+    fn line_prefix() {
+        let value = json!({"a": [1], "b": 3});
+        let formatter = OpenStructures::new("  ", 1).with_line_prefix("// ");
+        assert_eq!(format(formatter, &value), "{\n//   \"a\": [1],\n//   \"b\": 3\n// }");
+    }
+
+    #[test]
+    fn comma_first_empty_members_inline() {
+        let value = json!({"a": {"b": [1, 2], "c": {}}, "d": []});
+        let formatter = OpenStructures::new("  ", 3).with_comma_first(true);
+        assert_eq!(
+            format(formatter, &value),
+            "{ \"a\":\n  { \"b\":\n    [ 1\n    , 2\n    ]\n  , \"c\": {}\n  }\n, \"d\": []\n}"
+        );
+    }
+
+    #[test]
+    fn nested_structure_above_element_limit_expands_parents() {
+        let value = json!({"x": {"y": (0..20).collect::<Vec<_>>()}});
+        let formatter = OpenStructures::new("  ", 0)
+            .with_fold_policy(FoldPolicy::new().expand_above_elements(5));
+        let y = (0..20).map(|i| format!("      {}", i)).collect::<Vec<_>>().join(",\n");
+        assert_eq!(format(formatter, &value), format!("{{\n  \"x\": {{\n    \"y\": [\n{}\n    ]\n  }}\n}}", y));
+
+        let value = json!([{"a": 1}, [1, 2, 3, 4, 5, 6, 7]]);
+        let formatter = OpenStructures::new("  ", 0)
+            .with_fold_policy(FoldPolicy::new().expand_above_elements(5));
+        assert_eq!(
+            format(formatter, &value),
+            "[\n  {\"a\": 1},\n  [\n    1,\n    2,\n    3,\n    4,\n    5,\n    6,\n    7\n  ]\n]"
+        );
+    }
+
+    #[test]
+    fn expanded_rule_expands_folded_parents() {
+        let value = json!({"a": {"b": [1, 2], "c": []}, "d": 1});
+        let rules = LayoutRules::new().with_rule("$..b".parse().unwrap(), Layout::Expand);
+        let formatter = OpenStructures::new("  ", 0).with_layout_rules(rules);
+        assert_eq!(format(formatter, &value), "{\n  \"a\": {\n    \"b\": [\n      1,\n      2\n    ],\n    \"c\": []\n  },\n  \"d\": 1\n}");
+
+        let rules = LayoutRules::new().with_rule("$.a.c".parse().unwrap(), Layout::Expand);
+        let formatter = OpenStructures::new("  ", 0).with_layout_rules(rules);
+        assert_eq!(format(formatter, &value), "{\"a\": {\"b\": [1, 2], \"c\": []}, \"d\": 1}");
+    }
+}
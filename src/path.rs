@@ -0,0 +1,145 @@
+use std::fmt;
+use std::fmt::Write;
+
+pub(crate) type PathHook<'a> = Box<dyn FnMut(&Path) + 'a>;
+
+/// One step from a structure into one of its values.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PathSegment {
+    /// the member of an object with this (unescaped) key
+    Key(String),
+    /// the element of an array at this position
+    Index(usize),
+}
+
+/// The location of a value inside the written document, starting at the top level value.
+///
+/// Both formatters keep track of the path of the value they are currently writing and pass it to
+/// the hook set by `with_path_hook`. While an object key is written the path still points to the
+/// object.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+    // for every opened array the index of its next element
+    indices: Vec<usize>,
+}
+
+impl Path {
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// count of structures around the value, 0 for the top level value
+    pub fn depth(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// The path as JSON Pointer (RFC 6901), e.g. `/childs/0/name`. The top level value is `""`.
+    pub fn json_pointer(&self) -> String {
+        let mut pointer = String::new();
+        for segment in &self.segments {
+            pointer.push('/');
+            match segment {
+                PathSegment::Key(key) => {
+                    pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                }
+                PathSegment::Index(index) => {
+                    write!(pointer, "{}", index).unwrap();
+                }
+            }
+        }
+        pointer
+    }
+
+    /// The path as JSONPath, e.g. `$.childs[0].name`.
+    /// Keys which aren't simple names are written in bracket notation like `$['a b']`.
+    pub fn json_path(&self) -> String {
+        let mut path = String::from("$");
+        for segment in &self.segments {
+            match segment {
+                PathSegment::Key(key) if is_name(key) => {
+                    path.push('.');
+                    path.push_str(key);
+                }
+                PathSegment::Key(key) => {
+                    path.push_str("['");
+                    for c in key.chars() {
+                        if c == '\\' || c == '\'' {
+                            path.push('\\');
+                        }
+                        path.push(c);
+                    }
+                    path.push_str("']");
+                }
+                PathSegment::Index(index) => {
+                    write!(path, "[{}]", index).unwrap();
+                }
+            }
+        }
+        path
+    }
+
+    pub(crate) fn begin_array(&mut self) {
+        self.indices.push(0);
+    }
+    pub(crate) fn end_array(&mut self) {
+        self.indices.pop();
+    }
+    pub(crate) fn begin_element(&mut self) {
+        let index = self.indices.last_mut().expect("array element outside of array");
+        self.segments.push(PathSegment::Index(*index));
+        *index += 1;
+    }
+    /// The next element of the innermost array is `count` positions further.
+    pub(crate) fn skip_elements(&mut self, count: usize) {
+        let index = self.indices.last_mut().expect("array element outside of array");
+        *index += count;
+    }
+    pub(crate) fn begin_member(&mut self, key: String) {
+        self.segments.push(PathSegment::Key(key));
+    }
+    /// Called after every array element and object member.
+    pub(crate) fn end_value(&mut self) {
+        self.segments.pop();
+    }
+}
+
+/// Writes the path as JSONPath.
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.json_path())
+    }
+}
+
+/// Checks if `key` can be written in JSONPath dot notation.
+fn is_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|c| c == '_' || c.is_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{CompactPrettyFormatter, FoldPolicy, OpenStructures};
+    use serde_json::json;
+
+    #[test]
+    fn path_hook_sees_every_value() {
+        let value = json!({"a": [1, {"b": 2.5}], "c": "x"});
+        let expected = ["$", "$.a", "$.a[0]", "$.a[1]", "$.a[1].b", "$.c"];
+        let mut paths = vec![];
+        // the lookahead of the fold policy records the values before they are written
+        let formatter = OpenStructures::new("  ", 0)
+            .with_fold_policy(FoldPolicy::new().expand_above_elements(3))
+            .with_path_hook(|path| paths.push(path.to_string()));
+        format(formatter, &value);
+        assert_eq!(paths, expected);
+        let mut paths = vec![];
+        format(CompactPrettyFormatter::new(80, "  ", true).with_path_hook(|path| paths.push(path.to_string())), &value);
+        assert_eq!(paths, expected);
+    }
+}
@@ -0,0 +1,228 @@
+use std::borrow::Cow;
+
+/// Settings of the preview mode, which shortens the output for logs and previews.
+///
+/// The output of the preview mode is lossy, it is meant to be read by humans and not to be
+/// parsed back. By default nothing is shortened.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Preview {
+    max_string_length: Option<usize>,
+    array_elision: Option<(usize, usize)>,
+    elision_marker: ElisionMarker,
+    max_depth: Option<usize>,
+    depth_placeholder: DepthPlaceholder,
+}
+
+/// What replaces the structures below the maximal depth.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum DepthPlaceholder {
+    /// `{…}` or `[…]`, which is not valid JSON.
+    #[default]
+    Ellipsis,
+    /// An empty structure with a comment counting its members like `{/* 12 keys */}` or
+    /// `[/* 3 elements */]`.
+    Count,
+}
+
+/// How the elements left out of an array are marked.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ElisionMarker {
+    /// A string element like `"… 998 more …"`.
+    #[default]
+    String,
+    /// A comment like `// 998 more` in front of the next element, written like the comments of
+    /// [`Comments`](crate::Comments).
+    Comment,
+}
+
+impl Preview {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Cuts string values wider than `length` display columns and marks the cut with an ellipsis
+    /// and the count of removed characters like `…(+2048 chars)`.
+    ///
+    /// Strings are only cut between grapheme clusters, which are approximated by keeping
+    /// combining marks, variation selectors, emoji modifiers, zero width joiner sequences and
+    /// flags together. A cluster takes two columns if it starts with a wide character like a CJK
+    /// ideograph or an emoji, one otherwise. Object keys are never cut.
+    pub fn max_string_length(mut self, length: usize) -> Self {
+        self.max_string_length = Some(length);
+        self
+    }
+
+    /// Writes only the first `head` and the last `tail` elements of arrays, the elements in
+    /// between are replaced by a marker, see [`ElisionMarker`].
+    ///
+    /// The elements behind the head are kept in memory until the end of the array is reached.
+    pub fn elide_arrays(mut self, head: usize, tail: usize) -> Self {
+        self.array_elision = Some((head, tail));
+        self
+    }
+    /// Sets how elided array elements are marked.
+    pub fn elision_marker(mut self, marker: ElisionMarker) -> Self {
+        self.elision_marker = marker;
+        self
+    }
+
+    /// Writes only structures nested up to `depth` levels, deeper ones are replaced by a
+    /// placeholder, see [`DepthPlaceholder`]. The top level structure has depth 1.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+    /// Sets what replaces the structures below the maximal depth.
+    pub fn depth_placeholder(mut self, placeholder: DepthPlaceholder) -> Self {
+        self.depth_placeholder = placeholder;
+        self
+    }
+
+    pub(crate) fn array_elision(&self) -> Option<(usize, usize)> {
+        self.array_elision
+    }
+    pub(crate) fn marker(&self) -> ElisionMarker {
+        self.elision_marker
+    }
+    pub(crate) fn depth_limit(&self) -> Option<usize> {
+        self.max_depth
+    }
+    pub(crate) fn placeholder(&self) -> DepthPlaceholder {
+        self.depth_placeholder
+    }
+
+    /// the unescaped string value `raw` as written in the preview
+    pub(crate) fn truncate<'s>(&self, raw: &'s str) -> Cow<'s, str> {
+        let cut = self.max_string_length.and_then(|length| {
+            let mut width = 0;
+            let mut start = 0;
+            for end in grapheme_boundaries(raw) {
+                width += cluster_width(&raw[start..end]);
+                if width > length {
+                    return Some(start);
+                }
+                start = end;
+            }
+            None
+        });
+        match cut {
+            Some(cut) => {
+                let removed = raw[cut..].chars().count();
+                Cow::Owned(format!("{}…(+{} chars)", &raw[..cut], removed))
+            }
+            _ => Cow::Borrowed(raw),
+        }
+    }
+}
+
+/// The byte indices at which the grapheme clusters of `text` end.
+fn grapheme_boundaries(text: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (_, first) = chars.next()?;
+        let mut previous = first;
+        let mut regional_indicators = usize::from(is_regional_indicator(first));
+        while let Some(&(index, c)) = chars.peek() {
+            let joined = previous == '\u{200D}'
+                || is_extender(c)
+                || (previous == '\r' && c == '\n')
+                // flags are pairs of regional indicators
+                || (is_regional_indicator(c) && regional_indicators % 2 == 1);
+            if !joined {
+                return Some(index);
+            }
+            if is_regional_indicator(c) {
+                regional_indicators += 1;
+            }
+            previous = c;
+            chars.next();
+        }
+        Some(text.len())
+    })
+}
+
+/// the display columns taken by `text`
+pub(crate) fn display_width(text: &str) -> usize {
+    let mut start = 0;
+    grapheme_boundaries(text)
+        .map(|end| cluster_width(&text[std::mem::replace(&mut start, end)..end]))
+        .sum()
+}
+
+/// the display columns taken by a grapheme cluster
+fn cluster_width(cluster: &str) -> usize {
+    let mut chars = cluster.chars();
+    match chars.next() {
+        // flags
+        Some(c) if is_regional_indicator(c) && chars.next().is_some() => 2,
+        Some(c) if is_wide(c) => 2,
+        _ => 1,
+    }
+}
+
+/// characters taking two columns, approximated by the blocks of East Asian wide and fullwidth
+/// characters and emoji
+fn is_wide(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{115F}'
+        | '\u{231A}'..='\u{231B}'
+        | '\u{2E80}'..='\u{303E}'
+        | '\u{3041}'..='\u{33FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{A000}'..='\u{A4CF}'
+        | '\u{AC00}'..='\u{D7A3}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{1F300}'..='\u{1F64F}'
+        | '\u{1F680}'..='\u{1F6FF}'
+        | '\u{1F900}'..='\u{1F9FF}'
+        | '\u{20000}'..='\u{3FFFD}'
+    )
+}
+
+/// characters which belong to the grapheme cluster of the character in front of them
+fn is_extender(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}'
+        | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Preview;
+
+    fn truncated(length: usize, raw: &str) -> String {
+        Preview::new().max_string_length(length).truncate(raw).into_owned()
+    }
+
+    #[test]
+    fn cuts_at_display_columns() {
+        assert_eq!(truncated(3, "abcdef"), "abc…(+3 chars)");
+        assert_eq!(truncated(3, "abc"), "abc");
+        assert_eq!(truncated(4, "日本語です"), "日本…(+3 chars)");
+        assert_eq!(truncated(3, "ab日本"), "ab…(+2 chars)");
+        assert_eq!(truncated(0, "a"), "…(+1 chars)");
+    }
+
+    #[test]
+    fn keeps_grapheme_clusters_together() {
+        assert_eq!(truncated(2, "e\u{301}e\u{301}e\u{301}"), "e\u{301}e\u{301}…(+2 chars)");
+        assert_eq!(truncated(2, "👍🏽👍🏽"), "👍🏽…(+2 chars)");
+        assert_eq!(truncated(4, "🇩🇪🇫🇷🇮🇹"), "🇩🇪🇫🇷…(+2 chars)");
+    }
+}
@@ -0,0 +1,104 @@
+use crate::path::Path;
+use crate::selector::Selector;
+
+/// Removes members and array elements from the output, like a projection in `jq`.
+///
+/// Values matching a dropped selector are removed with their key. If any selectors are kept, only
+/// the values they match are written, together with the structures leading to them. The top level
+/// value is always written.
+#[derive(Clone, Debug, Default)]
+pub struct Projection {
+    dropped: Vec<Selector>,
+    kept: Vec<Selector>,
+}
+
+/// If a value is written
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Visibility {
+    Dropped,
+    /// the value with everything inside
+    Kept,
+    /// the value only if it is a structure, as kept values may be inside
+    Ancestor,
+}
+
+impl Projection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Drops the values at the paths matching `selector`, e.g. `$..internal`.
+    pub fn with_dropped(mut self, selector: Selector) -> Self {
+        self.dropped.push(selector);
+        self
+    }
+    /// Adds `selector` to the allow-list, e.g. `$.items[*].name`.
+    pub fn with_kept(mut self, selector: Selector) -> Self {
+        self.kept.push(selector);
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.kept.is_empty()
+    }
+
+    /// the visibility of the value at `path`, `in_kept` if a structure around it is kept
+    pub(crate) fn visibility(&self, path: &Path, in_kept: bool) -> Visibility {
+        if self.dropped.iter().any(|selector| selector.matches(path)) {
+            Visibility::Dropped
+        } else if in_kept || self.kept.is_empty() || self.kept.iter().any(|selector| selector.matches(path)) {
+            Visibility::Kept
+        } else if self.kept.iter().any(|selector| selector.leads_to(path)) {
+            Visibility::Ancestor
+        } else {
+            Visibility::Dropped
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{OpenStructures, Preview, Projection, Redaction, Selector};
+    use serde_json::json;
+
+    fn selector(selector: &str) -> Selector {
+        selector.parse().unwrap()
+    }
+    // writes everything in one line
+    fn folded() -> OpenStructures<'static> {
+        OpenStructures::new("  ", 0)
+    }
+
+    #[test]
+    fn projection_of_nested_structures() {
+        let value = json!({"a": 1, "b": {"c": [{"d": 1, "e": 2}, {"e": 3}], "f": 4}, "g": [5]});
+        let projection = Projection::new().with_kept(selector("$.b.c[*].e")).with_kept(selector("$.g"));
+        assert_eq!(
+            format(folded().with_projection(projection), &value),
+            r#"{"b": {"c": [{"e": 2}, {"e": 3}]}, "g": [5]}"#
+        );
+        let projection = Projection::new().with_kept(selector("$.b")).with_dropped(selector("$..e"));
+        assert_eq!(
+            format(folded().with_projection(projection), &value),
+            r#"{"b": {"c": [{"d": 1}, {}], "f": 4}}"#
+        );
+    }
+
+    #[test]
+    fn dropped_first_members() {
+        let value = json!({"a": 1, "b": [1, 2, 3], "c": 3});
+        let projection = Projection::new().with_dropped(selector("$.a")).with_dropped(selector("$.b[0]"));
+        let formatter = OpenStructures::new("  ", 1).with_comma_first(true).with_projection(projection);
+        assert_eq!(format(formatter, &value), "{ \"b\": [2, 3]\n, \"c\": 3\n}");
+    }
+
+    #[test]
+    fn projection_redaction_and_depth() {
+        let value = json!({"keep": {"password": "x", "deep": {"deeper": [1]}}, "drop": 1});
+        let formatter = folded()
+            .with_projection(Projection::new().with_kept(selector("$.keep")))
+            .with_redaction(Redaction::new().with_key("password"))
+            .with_preview(Preview::new().max_depth(2));
+        assert_eq!(format(formatter, &value), r#"{"keep": {"deep": {…}, "password": "***"}}"#);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::path::Path;
+use crate::selector::Selector;
+use std::collections::HashSet;
+
+type KeyPredicate<'a> = Box<dyn Fn(&str) -> bool + 'a>;
+
+/// Hides the values of secret members, like passwords and tokens.
+///
+/// Values are selected by the key of their member, by a predicate on that key (e.g. a regular
+/// expression of the `regex` crate) or by a [`Selector`], which can also select array elements and
+/// the top level value `$`.
+/// The tokens of a selected value are never written, the value is replaced as configured by
+/// [`Replacement`].
+#[derive(Default)]
+pub struct Redaction<'a> {
+    keys: HashSet<String>,
+    predicates: Vec<KeyPredicate<'a>>,
+    selectors: Vec<Selector>,
+    replacement: Replacement,
+}
+
+/// What replaces a redacted value.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Replacement {
+    /// The string `"***"`.
+    #[default]
+    Mask,
+    /// A string like `"#8c1f3a6b0d2e4f57"` with a hash of the value, so equal values can be
+    /// recognized. The hash is not cryptographic, short values can be found by trying.
+    Hash,
+}
+
+impl<'a> Redaction<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Redacts the values of all members with this key.
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.keys.insert(key.into());
+        self
+    }
+    /// Redacts the values of all members whose key matches `predicate`.
+    pub fn with_key_matching(mut self, predicate: impl Fn(&str) -> bool + 'a) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+    /// Redacts the values at the paths matching `selector`.
+    pub fn with_selector(mut self, selector: Selector) -> Self {
+        self.selectors.push(selector);
+        self
+    }
+    /// Sets what replaces redacted values.
+    pub fn with_replacement(mut self, replacement: Replacement) -> Self {
+        self.replacement = replacement;
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.predicates.is_empty() && self.selectors.is_empty()
+    }
+    pub(crate) fn replacement(&self) -> Replacement {
+        self.replacement
+    }
+
+    /// true if the value at `path` is redacted, `key` is the key of its member
+    pub(crate) fn redacts(&self, key: Option<&str>, path: &Path) -> bool {
+        let by_key = key.is_some_and(|key| {
+            self.keys.contains(key) || self.predicates.iter().any(|predicate| predicate(key))
+        });
+        by_key || self.selectors.iter().any(|selector| selector.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{CompactPrettyFormatter, OpenStructures, Redaction, Replacement};
+    use serde_json::json;
+
+    // writes everything in one line
+    fn folded() -> OpenStructures<'static> {
+        OpenStructures::new("  ", 0)
+    }
+
+    #[test]
+    fn redacted_structures() {
+        let value = json!({"user": {"name": "a", "token": {"value": [1, 2]}}, "tokens": [{"token": "x"}, "y"]});
+        let redaction = Redaction::new().with_key("token").with_selector("$.tokens[1]".parse().unwrap());
+        assert_eq!(
+            format(folded().with_redaction(redaction), &value),
+            r#"{"tokens": [{"token": "***"}, "***"], "user": {"name": "a", "token": "***"}}"#
+        );
+    }
+
+    #[test]
+    fn hashed_redaction() {
+        let value = json!({"a": "secret", "b": "secret", "c": "other", "d": ["secret"]});
+        let redaction = Redaction::new()
+            .with_key_matching(|key| key != "d")
+            .with_selector("$.d".parse().unwrap())
+            .with_replacement(Replacement::Hash);
+        let formatted = format(folded().with_redaction(redaction), &value);
+        let hashes: Vec<&str> = formatted.split('"').filter(|part| part.starts_with('#')).collect();
+        assert_eq!(hashes.len(), 4);
+        assert_eq!(hashes[0], hashes[1]);
+        assert_ne!(hashes[0], hashes[2]);
+        assert_ne!(hashes[0], hashes[3]);
+    }
+
+    #[test]
+    fn redacted_top_level_value() {
+        let redaction = || Redaction::new().with_selector("$".parse().unwrap());
+        assert_eq!(format(folded().with_redaction(redaction()), &json!({"a": [1]})), r#""***""#);
+        assert_eq!(format(folded().with_redaction(redaction()), &"secret"), r#""***""#);
+        let formatter = CompactPrettyFormatter::new(80, "  ", true).with_redaction(redaction());
+        assert_eq!(format(formatter, &json!([1, 2])), r#""***""#);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::comments::Comments;
+use crate::path::{Path, PathSegment};
+use serde_json::Value;
+
+// protects against `$ref` cycles
+const MAX_REF_DEPTH: usize = 32;
+
+/// Creates [`Comments`] from the `description`s of a JSON Schema document.
+///
+/// For every written value the matching subschema is looked up by following `properties`,
+/// `additionalProperties`, `items` (also in its tuple form and as `prefixItems`) and local
+/// `$ref`s like `#/definitions/Child`. Its `description` and optionally its `default` and `enum`
+/// become the comment of the value.
+pub struct SchemaComments<'a> {
+    schema: &'a Value,
+    defaults: bool,
+    enums: bool,
+}
+
+impl<'a> SchemaComments<'a> {
+    pub fn new(schema: &'a Value) -> Self {
+        SchemaComments {
+            schema,
+            defaults: false,
+            enums: false,
+        }
+    }
+    /// Adds the `default` of a subschema to its comment.
+    pub fn with_defaults(mut self, defaults: bool) -> Self {
+        self.defaults = defaults;
+        self
+    }
+    /// Adds the allowed values of an `enum` subschema to its comment.
+    pub fn with_enums(mut self, enums: bool) -> Self {
+        self.enums = enums;
+        self
+    }
+
+    /// follows `$ref`s until the schema isn't a reference any more
+    fn resolve(&self, mut schema: &'a Value) -> &'a Value {
+        for _ in 0..MAX_REF_DEPTH {
+            let target = schema.get("$ref")
+                .and_then(Value::as_str)
+                .and_then(|reference| reference.strip_prefix('#'))
+                .and_then(|pointer| self.schema.pointer(pointer));
+            match target {
+                Some(target) => schema = target,
+                None => break,
+            }
+        }
+        schema
+    }
+
+    /// the subschema describing the value at `path`
+    fn schema_at(&self, path: &Path) -> Option<&'a Value> {
+        let mut schema = self.schema;
+        for segment in path.segments() {
+            let resolved = self.resolve(schema);
+            schema = match segment {
+                PathSegment::Key(key) => resolved.get("properties")
+                    .and_then(|properties| properties.get(key))
+                    .or_else(|| resolved.get("additionalProperties").filter(|schema| schema.is_object()))?,
+                PathSegment::Index(index) => {
+                    let tuple = resolved.get("prefixItems")
+                        .or_else(|| resolved.get("items").filter(|items| items.is_array()));
+                    match tuple {
+                        Some(tuple) => tuple.get(*index)
+                            .or_else(|| resolved.get("additionalItems"))
+                            .or_else(|| resolved.get("items").filter(|items| items.is_object()))?,
+                        None => resolved.get("items")?,
+                    }
+                }
+            };
+        }
+        Some(schema)
+    }
+
+    fn comment(&self, path: &Path) -> Option<String> {
+        // keywords next to a `$ref` take precedence over the ones of the referenced schema
+        let schema = self.schema_at(path)?;
+        let resolved = self.resolve(schema);
+        let keyword = |name: &str| schema.get(name).or_else(|| resolved.get(name));
+
+        let mut lines = vec![];
+        if let Some(description) = keyword("description").and_then(Value::as_str) {
+            lines.push(description.to_string());
+        }
+        if self.defaults {
+            if let Some(default) = keyword("default") {
+                lines.push(format!("Default: {}", default));
+            }
+        }
+        if self.enums {
+            if let Some(values) = keyword("enum").and_then(Value::as_array) {
+                let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                lines.push(format!("Allowed values: {}", values.join(", ")));
+            }
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+}
+
+impl<'a> From<SchemaComments<'a>> for Comments<'a> {
+    fn from(schema: SchemaComments<'a>) -> Self {
+        Comments::new().with_callback(move |path| schema.comment(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::format;
+    use crate::{OpenStructures, SchemaComments};
+    use serde_json::json;
+
+    #[test]
+    fn comments_from_descriptions() {
+        let schema = json!({
+            "properties": {
+                "name": {"description": "the name"},
+                "tags": {"items": {"description": "a tag"}},
+                "child": {"$ref": "#/definitions/Child"},
+                "pair": {"items": [{"description": "first"}, {"description": "second"}]},
+            },
+            "additionalProperties": {"description": "anything else"},
+            "definitions": {
+                "Child": {"description": "a child", "properties": {"age": {"description": "in years"}}}
+            }
+        });
+        let value = json!({"name": "x", "tags": ["a"], "child": {"age": 3}, "pair": [1, 2], "other": null});
+        let formatter = OpenStructures::new("  ", 0).with_comments(SchemaComments::new(&schema).into());
+        assert_eq!(
+            format(formatter, &value),
+            "{\"child\": {\"age\": 3 /* in years */} /* a child */, \"name\": \"x\" /* the name */, \
+             \"other\": null /* anything else */, \"pair\": [1 /* first */, 2 /* second */], \
+             \"tags\": [\"a\" /* a tag */]}"
+        );
+    }
+
+    #[test]
+    fn defaults_and_enums() {
+        let schema = json!({
+            "properties": {
+                "mode": {"description": "the mode", "default": "fast", "enum": ["fast", "slow"]},
+            }
+        });
+        let value = json!({"mode": "slow"});
+        let formatter = OpenStructures::new("  ", 0)
+            .with_comments(SchemaComments::new(&schema).with_defaults(true).with_enums(true).into());
+        assert_eq!(
+            format(formatter, &value),
+            r#"{"mode": "slow" /* the mode Default: "fast" Allowed values: "fast", "slow" */}"#
+        );
+    }
+
+    #[test]
+    fn reference_cycles_end() {
+        let schema = json!({"$ref": "#/definitions/a", "definitions": {"a": {"$ref": "#/definitions/a"}}});
+        let formatter = OpenStructures::new("  ", 0).with_comments(SchemaComments::new(&schema).into());
+        assert_eq!(format(formatter, &json!({"x": 1})), r#"{"x": 1}"#);
+    }
+}
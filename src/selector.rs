@@ -0,0 +1,281 @@
+use crate::path::{Path, PathSegment};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A JSONPath-like expression selecting values by their [`Path`].
+///
+/// Supported are the root `$`, members `.name` and `['name']`, indices `[0]`, wildcards `.*` and
+/// `[*]` and the descendant operator `..`, e.g. `$.childs[*].name` or `$..coordinates`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Step {
+    // the step may skip any number of segments before it selects one (`..`)
+    descendant: bool,
+    name: Name,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Name {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl Name {
+    fn selects(&self, segment: &PathSegment) -> bool {
+        match (self, segment) {
+            (Name::Wildcard, _) => true,
+            (Name::Key(name), PathSegment::Key(key)) => name == key,
+            (Name::Index(index), PathSegment::Index(other)) => index == other,
+            _ => false,
+        }
+    }
+}
+
+impl Selector {
+    pub fn matches(&self, path: &Path) -> bool {
+        matches_from(&self.steps, path.segments())
+    }
+    /// true if a value below `path` may match
+    pub(crate) fn leads_to(&self, path: &Path) -> bool {
+        leads_from(&self.steps, path.segments())
+    }
+}
+
+fn matches_from(steps: &[Step], segments: &[PathSegment]) -> bool {
+    match steps.split_first() {
+        None => segments.is_empty(),
+        Some((step, rest)) if step.descendant => (0..segments.len()).any(|skip| {
+            step.name.selects(&segments[skip]) && matches_from(rest, &segments[skip + 1..])
+        }),
+        Some((step, rest)) => match segments.split_first() {
+            Some((segment, segments)) => step.name.selects(segment) && matches_from(rest, segments),
+            None => false,
+        },
+    }
+}
+
+fn leads_from(steps: &[Step], segments: &[PathSegment]) -> bool {
+    match steps.split_first() {
+        None => false,
+        // `..` may skip all of the remaining segments
+        Some((step, _)) if step.descendant => true,
+        Some((step, rest)) => match segments.split_first() {
+            Some((segment, segments)) => step.name.selects(segment) && leads_from(rest, segments),
+            None => true,
+        },
+    }
+}
+
+/// The error returned if a selector can't be parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SelectorError {
+    selector: String,
+    position: usize,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid selector `{}` at position {}", self.selector, self.position)
+    }
+}
+
+impl Error for SelectorError {}
+
+impl FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            selector,
+            position: 0,
+        };
+        parser.expect('$')?;
+        let mut steps = vec![];
+        while let Some(c) = parser.peek() {
+            let descendant = parser.eat("..");
+            let name = if c == '[' || parser.peek() == Some('[') {
+                parser.bracket()?
+            } else {
+                if !descendant {
+                    parser.expect('.')?;
+                }
+                parser.name()?
+            };
+            steps.push(Step {
+                descendant,
+                name,
+            });
+        }
+        Ok(Selector { steps })
+    }
+}
+
+struct Parser<'a> {
+    selector: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> SelectorError {
+        SelectorError {
+            selector: self.selector.to_string(),
+            position: self.position,
+        }
+    }
+    fn rest(&self) -> &'a str {
+        &self.selector[self.position..]
+    }
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+    fn expect(&mut self, c: char) -> Result<(), SelectorError> {
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+    // a name in dot notation
+    fn name(&mut self) -> Result<Name, SelectorError> {
+        if self.eat("*") {
+            return Ok(Name::Wildcard);
+        }
+        let length = self.rest().find(['.', '[']).unwrap_or(self.rest().len());
+        if length == 0 {
+            return Err(self.error());
+        }
+        let name = &self.rest()[..length];
+        self.position += length;
+        Ok(Name::Key(name.to_string()))
+    }
+    // `[*]`, `[0]`, `['name']` or `["name"]`
+    fn bracket(&mut self) -> Result<Name, SelectorError> {
+        self.expect('[')?;
+        let name = match self.peek() {
+            Some('*') => {
+                self.position += 1;
+                Name::Wildcard
+            }
+            Some(quote) if quote == '\'' || quote == '"' => {
+                self.position += 1;
+                let mut key = String::new();
+                let mut chars = self.rest().char_indices();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => key.push(c),
+                            None => return Err(self.error()),
+                        },
+                        Some((index, c)) if c == quote => {
+                            self.position += index + 1;
+                            break;
+                        }
+                        Some((_, c)) => key.push(c),
+                        None => return Err(self.error()),
+                    }
+                }
+                Name::Key(key)
+            }
+            _ => {
+                let length = self.rest().find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest().len());
+                let index = self.rest()[..length].parse().map_err(|_| self.error())?;
+                self.position += length;
+                Name::Index(index)
+            }
+        };
+        self.expect(']')?;
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selector;
+    use crate::path::{Path, PathSegment};
+
+    fn path(segments: &[PathSegment]) -> Path {
+        let mut path = Path::default();
+        for segment in segments {
+            match segment {
+                PathSegment::Key(key) => path.begin_member(key.clone()),
+                PathSegment::Index(index) => {
+                    path.begin_array();
+                    for _ in 0..*index {
+                        path.begin_element();
+                        path.end_value();
+                    }
+                    path.begin_element();
+                }
+            }
+        }
+        path
+    }
+
+    fn key(key: &str) -> PathSegment {
+        PathSegment::Key(key.to_string())
+    }
+
+    fn matches(selector: &str, segments: &[PathSegment]) -> bool {
+        selector.parse::<Selector>().unwrap().matches(&path(segments))
+    }
+
+    #[test]
+    fn members_indices_and_wildcards() {
+        let name = [key("childs"), PathSegment::Index(1), key("name")];
+        assert!(matches("$.childs[1].name", &name));
+        assert!(matches("$.childs[*].name", &name));
+        assert!(matches("$['childs'][1][\"name\"]", &name));
+        assert!(matches("$.*.*.*", &name));
+        assert!(!matches("$.childs[0].name", &name));
+        assert!(!matches("$.childs[1]", &name));
+        assert!(matches("$", &[]));
+        assert!(!matches("$", &name));
+    }
+
+    #[test]
+    fn descendants() {
+        let name = [key("childs"), PathSegment::Index(1), key("name")];
+        assert!(matches("$..name", &name));
+        assert!(matches("$..[1].name", &name));
+        assert!(matches("$..childs..name", &name));
+        assert!(!matches("$..childs", &name));
+        assert!(matches("$..*", &name));
+    }
+
+    #[test]
+    fn quoted_keys() {
+        assert!(matches("$['a.b']", &[key("a.b")]));
+        assert!(matches(r"$['it\'s']", &[key("it's")]));
+        assert!(matches("$[\"[x]\"]", &[key("[x]")]));
+    }
+
+    #[test]
+    fn errors_point_at_the_invalid_position() {
+        let position = |selector: &str| selector.parse::<Selector>().unwrap_err().position;
+        assert_eq!(position("a.b"), 0);
+        assert_eq!(position("$."), 2);
+        assert_eq!(position("$a"), 1);
+        assert_eq!(position("$[x]"), 2);
+        assert_eq!(position("$[1"), 3);
+        assert_eq!(position("$['a]"), 3);
+        assert_eq!(
+            "$.a[".parse::<Selector>().unwrap_err().to_string(),
+            "invalid selector `$.a[` at position 4"
+        );
+    }
+}
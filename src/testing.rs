@@ -0,0 +1,18 @@
+use serde::{Serialize, Serializer};
+use serde_json::ser::Formatter;
+
+/// Serializes `value` with `formatter`.
+pub(crate) fn format(formatter: impl Formatter, value: &impl Serialize) -> String {
+    let mut target = vec![];
+    value.serialize(&mut serde_json::Serializer::with_formatter(&mut target, formatter)).unwrap();
+    String::from_utf8(target).unwrap()
+}
+
+/// Serializes as byte array with `serialize_bytes`.
+pub(crate) struct Bytes(pub Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}